@@ -4,7 +4,7 @@ use regex::Regex;
 use std::{
     error::Error,
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Write},
     num::NonZeroUsize,
     ops::Range,
 };
@@ -26,6 +26,7 @@ pub struct Config {
     files: Vec<String>,
     delimiter: u8,
     extract: Extract,
+    no_split: bool,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -57,6 +58,11 @@ pub fn get_args() -> MyResult<Config> {
         .takes_value(true)
         .default_value("\t");
 
+    let no_split_args = Arg::with_name("no_split")
+        .short("n")
+        .help("Don't split multibyte characters with --bytes")
+        .requires("bytes");
+
     let field_args = Arg::with_name("fields")
         .short("f")
         .long("fields")
@@ -73,6 +79,7 @@ pub fn get_args() -> MyResult<Config> {
         .arg(char_args)
         .arg(delim_args)
         .arg(field_args)
+        .arg(no_split_args)
         .get_matches();
 
     let delimiter = matches.value_of("delim").unwrap();
@@ -91,9 +98,9 @@ pub fn get_args() -> MyResult<Config> {
     let extract = if let Some(field_pos) = fields {
         Fields(field_pos)
     } else if let Some(byte_pos) = bytes {
-        Fields(byte_pos)
+        Bytes(byte_pos)
     } else if let Some(char_pos) = chars {
-        Fields(char_pos)
+        Chars(char_pos)
     } else {
         return Err(From::from("Must have --fields, --bytes, or --chars"));
     };
@@ -102,6 +109,7 @@ pub fn get_args() -> MyResult<Config> {
         files: matches.values_of_lossy("files").unwrap(),
         delimiter: *delim_bytes.first().unwrap(),
         extract,
+        no_split: matches.is_present("no_split"),
     })
 }
 
@@ -109,7 +117,7 @@ pub fn run(config: Config) -> MyResult<()> {
     for filename in &config.files {
         match open(filename) {
             Err(err) => eprintln!("{}: {}", filename, err),
-            Ok(file) => match &config.extract {
+            Ok(mut file) => match &config.extract {
                 Fields(field_pos) => {
                     let mut reader = ReaderBuilder::new()
                         .delimiter(config.delimiter)
@@ -124,8 +132,19 @@ pub fn run(config: Config) -> MyResult<()> {
                     }
                 }
                 Bytes(byte_pos) => {
-                    for line in file.lines() {
-                        println!("{}", extract_bytes(&line?, byte_pos));
+                    let mut stdout = io::stdout().lock();
+                    let mut line = vec![];
+                    while file.read_until(b'\n', &mut line)? > 0 {
+                        if line.last() == Some(&b'\n') {
+                            line.pop();
+                        }
+                        let selected = match config.no_split {
+                            true => extract_whole_chars(&line, byte_pos),
+                            false => extract_bytes(&line, byte_pos),
+                        };
+                        stdout.write_all(&selected)?;
+                        stdout.write_all(b"\n")?;
+                        line.clear();
                     }
                 }
                 Chars(char_pos) => {
@@ -138,6 +157,7 @@ pub fn run(config: Config) -> MyResult<()> {
     }
     Ok(())
 }
+#[allow(clippy::obfuscated_if_else)]
 fn parse_index(input: &str) -> Result<usize, String> {
     let value_error = || format!("illegal list value: \"{}\"", input);
     input
@@ -151,6 +171,7 @@ fn parse_index(input: &str) -> Result<usize, String> {
         })
 }
 
+#[allow(clippy::useless_conversion)]
 fn parse_pos(range: &str) -> MyResult<PositionList> {
     let range_re = Regex::new(r"^(\d+)-(\d+)$").unwrap();
     range
@@ -184,17 +205,45 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
-fn extract_bytes(line: &str, byte_pos: &[Range<usize>]) -> String {
-    let mut s = vec![];
-    let bytes = line.as_bytes();
+fn extract_bytes(line: &[u8], byte_pos: &[Range<usize>]) -> Vec<u8> {
+    let mut selected = vec![];
     for range in byte_pos.iter().cloned() {
         for i in range {
-            if let Some(b) = bytes.get(i) {
-                s.push(*b)
+            if let Some(b) = line.get(i) {
+                selected.push(*b)
+            }
+        }
+    }
+    selected
+}
+
+// Like POSIX cut -n, a character is selected when its last byte is, and
+// bytes that aren't valid UTF-8 count as characters of their own
+fn extract_whole_chars(line: &[u8], byte_pos: &[Range<usize>]) -> Vec<u8> {
+    let mut char_ends = vec![];
+    let mut end = 0;
+    for chunk in line.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            end += c.len_utf8();
+            char_ends.push(end);
+        }
+        for _ in chunk.invalid() {
+            end += 1;
+            char_ends.push(end);
+        }
+    }
+
+    let mut selected = vec![];
+    for range in byte_pos {
+        let mut start = 0;
+        for &end in &char_ends {
+            if range.contains(&(end - 1)) {
+                selected.extend(&line[start..end]);
             }
+            start = end;
         }
     }
-    String::from_utf8_lossy(&s).into_owned()
+    selected
 }
 
 fn extract_chars(line: &str, char_pos: &[Range<usize>]) -> String {
@@ -221,10 +270,11 @@ fn extract_fields(record: &StringRecord, field_pos: &[Range<usize>]) -> Vec<Stri
 
 #[cfg(test)]
 mod unit_tests {
-    use super::{extract_bytes, extract_chars, extract_fields, parse_pos};
+    use super::{extract_bytes, extract_chars, extract_fields, extract_whole_chars, parse_pos};
     use csv::StringRecord;
 
     #[test]
+    #[allow(clippy::single_range_in_vec_init)]
     fn test_extract_fields() {
         let rec = StringRecord::from(vec!["Captain", "Sham", "12345"]);
         assert_eq!(extract_fields(&rec, &[0..1]), &["Captain"]);
@@ -235,6 +285,7 @@ mod unit_tests {
     }
 
     #[test]
+    #[allow(clippy::single_range_in_vec_init)]
     fn test_extract_chars() {
         assert_eq!(extract_chars("", &[0..1]), "".to_string());
         assert_eq!(extract_chars("ábc", &[0..1]), "á".to_string());
@@ -245,13 +296,32 @@ mod unit_tests {
     }
 
     #[test]
+    #[allow(clippy::single_range_in_vec_init)]
     fn test_extract_bytes() {
-        assert_eq!(extract_bytes("ábc", &[0..1]), "�".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..2]), "á".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..3]), "áb".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..4]), "ábc".to_string());
-        assert_eq!(extract_bytes("ábc", &[3..4, 2..3]), "cb".to_string());
-        assert_eq!(extract_bytes("ábc", &[0..2, 5..6]), "á".to_string());
+        let line = "ábc".as_bytes();
+        assert_eq!(extract_bytes(line, &[0..1]), b"\xc3");
+        assert_eq!(extract_bytes(line, &[0..2]), "á".as_bytes());
+        assert_eq!(extract_bytes(line, &[0..3]), "áb".as_bytes());
+        assert_eq!(extract_bytes(line, &[0..4]), "ábc".as_bytes());
+        assert_eq!(extract_bytes(line, &[3..4, 2..3]), b"cb");
+        assert_eq!(extract_bytes(line, &[0..2, 5..6]), "á".as_bytes());
+        assert_eq!(extract_bytes(b"a\xffb", &[1..3]), b"\xffb");
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_extract_whole_chars() {
+        let line = "ábc".as_bytes();
+        assert_eq!(extract_whole_chars(line, &[0..1]), b"");
+        assert_eq!(extract_whole_chars(line, &[1..2]), "á".as_bytes());
+        assert_eq!(extract_whole_chars(line, &[0..3]), "áb".as_bytes());
+        assert_eq!(extract_whole_chars(line, &[1..4]), "ábc".as_bytes());
+        assert_eq!(extract_whole_chars(line, &[3..4, 0..2]), "cá".as_bytes());
+        assert_eq!(extract_whole_chars(line, &[4..9]), b"");
+        assert_eq!(
+            extract_whole_chars(b"a\xff\xc3\xa9", &[1..2, 3..4]),
+            b"\xff\xc3\xa9"
+        );
     }
 
     #[test]
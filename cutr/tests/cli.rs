@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
@@ -159,14 +161,13 @@ fn run(args: &[&str], expected_file: &str) -> TestResult {
 }
 
 // --------------------------------------------------
-fn run_lossy(args: &[&str], expected_file: &str) -> TestResult {
-    let contents = fs::read(expected_file)?;
-    let expected = String::from_utf8_lossy(&contents);
+fn run_bytes(args: &[&str], expected_file: &str) -> TestResult {
+    let expected = fs::read(expected_file)?;
     Command::cargo_bin(PRG)?
         .args(args)
         .assert()
         .success()
-        .stdout(predicate::str::contains(expected));
+        .stdout(expected);
     Ok(())
 }
 
@@ -275,7 +276,7 @@ fn tsv_b2() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn tsv_b8() -> TestResult {
-    run_lossy(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
+    run_bytes(&[TSV, "-b", "8"], "tests/expected/movies1.tsv.b8.out")
 }
 
 // --------------------------------------------------
@@ -293,7 +294,7 @@ fn tsv_b2_3() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn tsv_b1_8() -> TestResult {
-    run_lossy(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
+    run_bytes(&[TSV, "-b", "1-8"], "tests/expected/movies1.tsv.b1-8.out")
 }
 
 // --------------------------------------------------
@@ -337,3 +338,130 @@ fn tsv_c1_8() -> TestResult {
 fn repeated_value() -> TestResult {
     run(&[BOOKS, "-c", "1,1"], "tests/expected/books.c1,1.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn csv_b1() -> TestResult {
+    run(&[CSV, "-b", "1"], "tests/expected/movies1.csv.b1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_b2() -> TestResult {
+    run(&[CSV, "-b", "2"], "tests/expected/movies1.csv.b2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_b8() -> TestResult {
+    run_bytes(&[CSV, "-b", "8"], "tests/expected/movies1.csv.b8.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_b1_2() -> TestResult {
+    run(&[CSV, "-b", "1-2"], "tests/expected/movies1.csv.b1-2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_b2_3() -> TestResult {
+    run(&[CSV, "-b", "2-3"], "tests/expected/movies1.csv.b2-3.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_b1_8() -> TestResult {
+    run_bytes(&[CSV, "-b", "1-8"], "tests/expected/movies1.csv.b1-8.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c1() -> TestResult {
+    run(&[CSV, "-c", "1"], "tests/expected/movies1.csv.c1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c2() -> TestResult {
+    run(&[CSV, "-c", "2"], "tests/expected/movies1.csv.c2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c8() -> TestResult {
+    run(&[CSV, "-c", "8"], "tests/expected/movies1.csv.c8.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c1_2() -> TestResult {
+    run(&[CSV, "-c", "1-2"], "tests/expected/movies1.csv.c1-2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c2_3() -> TestResult {
+    run(&[CSV, "-c", "2-3"], "tests/expected/movies1.csv.c2-3.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_c1_8() -> TestResult {
+    run(&[CSV, "-c", "1-8"], "tests/expected/movies1.csv.c1-8.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_b8_no_split() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([TSV, "-n", "-b", "8"])
+        .assert()
+        .success()
+        .stdout("e\ne\n\n");
+    Command::cargo_bin(PRG)?
+        .args([TSV, "-n", "-b", "8-9"])
+        .assert()
+        .success()
+        .stdout("ea\nes\né\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn tsv_b1_8_no_split() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([TSV, "-n", "-b", "1-8"])
+        .assert()
+        .success()
+        .stdout("title\tye\nThe Blue\nLes Mis\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn bytes_invalid_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-b", "2-4"])
+        .write_stdin(&b"ab\xffcd\nx\xc3\xa9"[..])
+        .assert()
+        .success()
+        .stdout(&b"b\xffc\n\xc3\xa9\n"[..]);
+    Command::cargo_bin(PRG)?
+        .args(["-n", "-b", "2-3"])
+        .write_stdin(&b"a\xff\xc3\xa9z\n"[..])
+        .assert()
+        .success()
+        .stdout(&b"\xff\n"[..]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_split_without_bytes() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([TSV, "-n", "-c", "1"])
+        .assert()
+        .failure();
+    Ok(())
+}
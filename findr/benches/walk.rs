@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{fs, path::Path};
+
+/// Fills `dir` with 20 directories of 20 directories of 20 files each.
+fn make_tree(dir: &Path) {
+    for i in 0..20 {
+        for j in 0..20 {
+            let sub = dir.join(format!("d{}", i)).join(format!("d{}", j));
+            fs::create_dir_all(&sub).unwrap();
+            for k in 0..20 {
+                fs::write(sub.join(format!("f{}.txt", k)), "").unwrap();
+            }
+        }
+    }
+}
+
+fn find(root: &Path, options: &[&str]) {
+    let args = options
+        .iter()
+        .map(|arg| arg.to_string())
+        .chain([root.to_string_lossy().into_owned()])
+        .chain(["-name".to_string(), "no-match".to_string()])
+        .collect();
+    findr::run(findr::parse_args(args).unwrap()).unwrap();
+}
+
+fn walk(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    make_tree(dir.path());
+
+    let mut group = c.benchmark_group("walk");
+    group.bench_function("serial", |b| b.iter(|| find(dir.path(), &[])));
+    for threads in ["2", "4", "8"] {
+        group.bench_function(format!("threads={}", threads), |b| {
+            b.iter(|| find(dir.path(), &["--threads", threads]))
+        });
+    }
+    group.bench_function("threads=4 sorted", |b| {
+        b.iter(|| find(dir.path(), &["--threads", "4", "--sort"]))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, walk);
+criterion_main!(benches);
@@ -0,0 +1,178 @@
+use crate::{
+    predicate::{size_unit, Visit},
+    MyResult,
+};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, Metadata},
+    hash::Hash,
+    io::{self, Read, Write},
+    mem,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// How much of each file is hashed before hashing all of it.
+const PARTIAL_BYTES: u64 = 4096;
+
+/// Gathers the regular files the expression is true for with
+/// `--duplicates`, to print those with identical contents.
+#[derive(Debug)]
+pub struct Duplicates {
+    min_size: u64,
+    files: Mutex<Vec<Candidate>>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    /// Device and inode, the same for hard links to one file, when known
+    id: Option<(u64, u64)>,
+}
+
+impl Duplicates {
+    pub fn new(min_size: u64) -> Duplicates {
+        Duplicates {
+            min_size,
+            files: Mutex::new(vec![]),
+        }
+    }
+
+    /// Notes the entry if it's a regular file of at least the minimum
+    /// size.
+    pub fn add(&self, visit: &mut Visit) {
+        let path = visit.entry.path().to_path_buf();
+        let Some(metadata) = visit.metadata() else {
+            return;
+        };
+        if metadata.is_file() && metadata.len() >= self.min_size {
+            self.files.lock().unwrap().push(Candidate {
+                path,
+                size: metadata.len(),
+                id: file_id(metadata),
+            });
+        }
+    }
+
+    /// Prints each group of files with the same contents, a path per line
+    /// and groups separated by a blank line. Files are grouped by size,
+    /// then by a hash of their start and then by a hash of all of them,
+    /// so most are never read in full. Hard links to one file count once
+    /// where they can be told apart.
+    pub fn print(&self, out: &mut impl Write) -> MyResult<()> {
+        let mut files = mem::take(&mut *self.files.lock().unwrap());
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut seen = HashSet::new();
+        files.retain(|file| file.id.is_none() || seen.insert(file.id));
+
+        let mut groups = vec![];
+        for same_size in group_by(files, |file| Some(file.size)) {
+            for same_start in group_by(same_size, |file| hash(file, PARTIAL_BYTES)) {
+                match same_start[0].size <= PARTIAL_BYTES {
+                    true => groups.push(same_start),
+                    false => groups.extend(group_by(same_start, |file| hash(file, u64::MAX))),
+                }
+            }
+        }
+
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            for file in group {
+                writeln!(out, "{}", file.path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the value of `--min-size`, a number of bytes or of the units of
+/// `-size`, like `10M`.
+pub fn parse_min_size(val: &str) -> MyResult<u64> {
+    let (num, unit) = match val.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&val[..i], c),
+        _ => (val, 'c'),
+    };
+    num.parse::<u64>()
+        .ok()
+        .zip(size_unit(unit))
+        .and_then(|(num, unit)| num.checked_mul(unit))
+        .ok_or_else(|| From::from(format!("Invalid --min-size \"{}\"", val)))
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Only Unix says which files are hard links to the same one.
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Splits `files` into groups with the same key, in order of their first
+/// file, leaving out files alone in their group and those without a key.
+fn group_by<K: Eq + Hash>(
+    files: Vec<Candidate>,
+    key: impl Fn(&Candidate) -> Option<K>,
+) -> Vec<Vec<Candidate>> {
+    let mut indexes: HashMap<K, usize> = HashMap::new();
+    let mut groups: Vec<Vec<Candidate>> = vec![];
+    for file in files {
+        let Some(key) = key(&file) else {
+            continue;
+        };
+        match indexes.get(&key) {
+            Some(&i) => groups[i].push(file),
+            None => {
+                indexes.insert(key, groups.len());
+                groups.push(vec![file]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// A hash of up to the first `limit` bytes of the file.
+fn hash(file: &Candidate, limit: u64) -> Option<blake3::Hash> {
+    let result = File::open(&file.path).and_then(|f| {
+        let mut hasher = blake3::Hasher::new();
+        io::copy(&mut f.take(limit), &mut hasher)?;
+        Ok(hasher.finalize())
+    });
+    match result {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            eprintln!("{}: {}", file.path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_min_size;
+
+    #[test]
+    fn test_parse_min_size() {
+        assert_eq!(parse_min_size("0").unwrap(), 0);
+        assert_eq!(parse_min_size("100").unwrap(), 100);
+        assert_eq!(parse_min_size("100c").unwrap(), 100);
+        assert_eq!(parse_min_size("2k").unwrap(), 2048);
+        assert_eq!(parse_min_size("1M").unwrap(), 1024 * 1024);
+        assert_eq!(parse_min_size("3b").unwrap(), 1536);
+        for bad in ["", "k", "-1", "1x", "1.5M", "99999999999G"] {
+            assert_eq!(
+                parse_min_size(bad).unwrap_err().to_string(),
+                format!("Invalid --min-size \"{}\"", bad)
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{walk::Entry, MyResult};
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Flush a batch once its arguments add up to this many bytes, well under
+/// the usual limits on a command line.
+const BATCH_BYTES: usize = 128 * 1024;
+
+/// A command run by `-exec`, `-execdir` or `-ok`, with `{}` standing for
+/// the entry's path.
+#[derive(Debug)]
+pub struct Exec {
+    command: Vec<String>,
+    /// Ended with `+`: run once for many entries instead of once each
+    batch: bool,
+    /// Run from the entry's directory on its `./`-prefixed name
+    in_dir: bool,
+    /// Ask before running
+    prompt: bool,
+    pending: Mutex<Batch>,
+    failed: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct Batch {
+    dir: Option<PathBuf>,
+    paths: Vec<OsString>,
+    bytes: usize,
+}
+
+impl Exec {
+    /// Parses the command following `-exec`, `-execdir` or `-ok`, up to the
+    /// `;` or `{} +` that ends it.
+    pub fn parse<'a>(name: &str, args: &mut impl Iterator<Item = &'a str>) -> MyResult<Exec> {
+        let mut command: Vec<String> = vec![];
+        let batch = loop {
+            match args.next() {
+                Some(";") => break false,
+                Some("+") if command.last().is_some_and(|arg| arg == "{}") => break true,
+                Some(arg) => command.push(arg.to_string()),
+                None => return Err(From::from(format!("Missing argument to --{}", name))),
+            }
+        };
+        let prompt = name == "ok";
+        if command.is_empty() || (batch && command.len() == 1) {
+            return Err(From::from(format!("Missing command for --{}", name)));
+        }
+        if batch && prompt {
+            return Err(From::from("--ok can't be ended with \"+\""));
+        }
+        if batch
+            && command[..command.len() - 1]
+                .iter()
+                .any(|arg| arg.contains("{}"))
+        {
+            return Err(From::from(format!(
+                "Only one instance of {{}} is supported with --{} ... +",
+                name
+            )));
+        }
+        Ok(Exec {
+            command,
+            batch,
+            in_dir: name == "execdir",
+            prompt,
+            pending: Mutex::new(Batch::default()),
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    /// Runs the command for `entry`, returning whether it succeeded. In a
+    /// batch the entry is only queued, and this is always true. `out` is
+    /// flushed first so the command's output comes after what was printed
+    /// before it.
+    pub fn execute(&self, entry: &Entry, out: &mut dyn Write) -> io::Result<bool> {
+        out.flush()?;
+        let (dir, path) = self.target(entry);
+        if self.batch {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.dir != dir || pending.bytes > BATCH_BYTES {
+                self.flush(&mut pending);
+                pending.dir = dir;
+            }
+            pending.bytes += path.len() + 1;
+            pending.paths.push(path);
+            return Ok(true);
+        }
+
+        let args: Vec<OsString> = self
+            .command
+            .iter()
+            .map(|arg| substitute(arg, &path))
+            .collect();
+        if self.prompt && !confirm(&args) {
+            return Ok(false);
+        }
+        Ok(self.spawn(&args[0], args[1..].iter().cloned(), dir.as_deref()))
+    }
+
+    /// The primary the command came from, as written on the command line.
+    pub fn primary(&self) -> &'static str {
+        match (self.in_dir, self.prompt) {
+            (_, true) => "-ok",
+            (true, false) => "-execdir",
+            (false, false) => "-exec",
+        }
+    }
+
+    /// Runs whatever is left of the batch, returning false if any run of
+    /// a batched command failed.
+    pub fn finish(&self) -> bool {
+        self.flush(&mut self.pending.lock().unwrap());
+        !self.failed.load(Ordering::Relaxed)
+    }
+
+    fn flush(&self, pending: &mut Batch) {
+        let batch = std::mem::take(pending);
+        if batch.paths.is_empty() {
+            return;
+        }
+        let fixed = &self.command[..self.command.len() - 1];
+        let args = fixed[1..].iter().map(OsString::from).chain(batch.paths);
+        if !self.spawn(fixed[0].as_ref(), args, batch.dir.as_deref()) {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The directory to run in, if not the current one, and what `{}`
+    /// stands for.
+    fn target(&self, entry: &Entry) -> (Option<PathBuf>, OsString) {
+        if !self.in_dir {
+            return (None, entry.path().as_os_str().to_owned());
+        }
+        let dir = match entry.path().parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (Some(dir), Path::new(".").join(entry.file_name()).into())
+    }
+
+    fn spawn(
+        &self,
+        program: &OsStr,
+        args: impl Iterator<Item = OsString>,
+        dir: Option<&Path>,
+    ) -> bool {
+        let mut command = Command::new(program);
+        command.args(args);
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        match command.status() {
+            Ok(status) => status.success(),
+            Err(e) => {
+                eprintln!("{}: {}", program.to_string_lossy(), e);
+                false
+            }
+        }
+    }
+}
+
+/// `arg` with each `{}` replaced by `path`, kept as an `OsStr` so that
+/// names that aren't UTF-8 are passed on as they are.
+fn substitute(arg: &str, path: &OsStr) -> OsString {
+    let mut parts = arg.split("{}");
+    let mut substituted = OsString::from(parts.next().unwrap_or_default());
+    for part in parts {
+        substituted.push(path);
+        substituted.push(part);
+    }
+    substituted
+}
+
+/// Asks on stderr whether to run `args`, reading the answer from stdin.
+/// Threads walking in parallel take turns.
+fn confirm(args: &[OsString]) -> bool {
+    static PROMPT: Mutex<()> = Mutex::new(());
+    let _turn = PROMPT.lock().unwrap();
+    let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    eprint!("< {} > ? ", args.join(" "));
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => answer.trim_start().starts_with(['y', 'Y']),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{substitute, Exec};
+    use std::ffi::OsStr;
+
+    fn parse(args: &[&str]) -> Result<Exec, String> {
+        Exec::parse("exec", &mut args.iter().copied()).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let exec = parse(&["echo", "x{}y", ";", "-print"]).unwrap();
+        assert_eq!(exec.command, ["echo", "x{}y"]);
+        assert!(!exec.batch);
+
+        let exec = parse(&["echo", "+", "{}", "+"]).unwrap();
+        assert_eq!(exec.command, ["echo", "+", "{}"]);
+        assert!(exec.batch);
+
+        assert_eq!(
+            parse(&["echo", "{}"]).unwrap_err(),
+            "Missing argument to --exec"
+        );
+        assert_eq!(parse(&[";"]).unwrap_err(), "Missing command for --exec");
+        assert_eq!(
+            parse(&["echo", "{}", "{}", "+"]).unwrap_err(),
+            "Only one instance of {} is supported with --exec ... +"
+        );
+        assert!(Exec::parse("ok", &mut ["rm", "{}", "+"].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let path = OsStr::new("a b");
+        assert_eq!(substitute("{}", path), "a b");
+        assert_eq!(substitute("x{}y{}", path), "xa bya b");
+        assert_eq!(substitute("echo", path), "echo");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_substitute_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = OsStr::from_bytes(b"a\xffb");
+        assert_eq!(substitute("{}", path).as_bytes(), b"a\xffb");
+        assert_eq!(substitute("x{}y{}", path).as_bytes(), b"xa\xffbya\xffb");
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{
+    exec::Exec,
+    predicate::{Predicate, Visit},
+    printf::Format,
+    MyResult,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    iter::Peekable,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// A find-style expression. `-a` binds tighter than `-o`, `!` tighter
+/// than both, and the right operand is skipped when the left one already
+/// decides the result.
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Predicate(Predicate),
+    Action(Action),
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Print(Format, Destination),
+    /// Don't descend into the current directory
+    Prune,
+    Delete,
+    Exec(Exec),
+}
+
+/// Where `-print` and its relatives write to. Files named by `-fprint`
+/// are created, or truncated, when the expression is parsed.
+#[derive(Debug)]
+pub enum Destination {
+    Stdout,
+    File(Mutex<BufWriter<File>>),
+}
+
+impl Expr {
+    /// Evaluates the expression for `visit`. Anything printed to standard
+    /// output goes to `out`.
+    pub fn eval(&self, visit: &mut Visit, out: &mut dyn Write) -> io::Result<bool> {
+        Ok(match self {
+            Expr::And(lhs, rhs) => lhs.eval(visit, out)? && rhs.eval(visit, out)?,
+            Expr::Or(lhs, rhs) => lhs.eval(visit, out)? || rhs.eval(visit, out)?,
+            Expr::Not(expr) => !expr.eval(visit, out)?,
+            Expr::Predicate(predicate) => predicate.matches(visit),
+            Expr::Action(Action::Print(format, destination)) => {
+                let mut buf = vec![];
+                format.render(visit, &mut buf);
+                match destination {
+                    Destination::Stdout => out.write_all(&buf)?,
+                    Destination::File(file) => file.lock().unwrap().write_all(&buf)?,
+                }
+                true
+            }
+            Expr::Action(Action::Prune) => {
+                visit.prune = true;
+                true
+            }
+            Expr::Action(Action::Delete) => delete(visit),
+            Expr::Action(Action::Exec(exec)) => exec.execute(visit.entry, out)?,
+        })
+    }
+
+    /// Runs the commands still waiting in `-exec ... +` batches and
+    /// flushes the files written by `-fprint`.
+    pub fn finish(&self) -> MyResult<()> {
+        match self {
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => {
+                let lhs = lhs.finish();
+                rhs.finish().and(lhs)
+            }
+            Expr::Not(expr) => expr.finish(),
+            Expr::Action(Action::Exec(exec)) => match exec.finish() {
+                true => Ok(()),
+                false => Err(From::from(format!(
+                    "A command run by {} ... + failed",
+                    exec.primary()
+                ))),
+            },
+            Expr::Action(Action::Print(_, Destination::File(file))) => {
+                Ok(file.lock().unwrap().flush()?)
+            }
+            Expr::Predicate(_) | Expr::Action(_) => Ok(()),
+        }
+    }
+
+    /// Whether the expression deletes entries, which needs a directory's
+    /// contents to be visited before the directory itself.
+    pub fn deletes(&self) -> bool {
+        self.any_action(&|action| matches!(action, Action::Delete))
+    }
+
+    /// Whether the expression does anything itself, in which case matching
+    /// entries aren't printed by default.
+    fn has_action(&self) -> bool {
+        self.any_action(&|action| !matches!(action, Action::Prune))
+    }
+
+    fn any_action(&self, f: &impl Fn(&Action) -> bool) -> bool {
+        match self {
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => lhs.any_action(f) || rhs.any_action(f),
+            Expr::Not(expr) => expr.any_action(f),
+            Expr::Predicate(_) => false,
+            Expr::Action(action) => f(action),
+        }
+    }
+}
+
+fn delete(visit: &Visit) -> bool {
+    let path = visit.entry.path();
+    let result = match visit.entry.file_type().is_dir() {
+        true => fs::remove_dir(path),
+        false => fs::remove_file(path),
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Cannot delete {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Parses the expression given after the paths. Global options found
+/// among it, like `-maxdepth`, evaluate as true and are added to
+/// `options`, which holds those given before the paths.
+pub fn parse(
+    args: &[String],
+    options: Vec<String>,
+    now: SystemTime,
+) -> MyResult<(Expr, Vec<String>)> {
+    let mut parser = Parser {
+        args: args.iter().map(String::as_str).peekable(),
+        now,
+        options,
+    };
+    if parser.args.peek().is_none() {
+        return Ok((print(), parser.options));
+    }
+
+    let expr = parser.parse_or()?;
+    if let Some(arg) = parser.args.next() {
+        return Err(From::from(format!("Unexpected \"{}\" in expression", arg)));
+    }
+    let expr = match expr.has_action() {
+        true => expr,
+        false => Expr::And(Box::new(expr), Box::new(print())),
+    };
+    Ok((expr, parser.options))
+}
+
+fn print() -> Expr {
+    Expr::Action(Action::Print(Format::path(b'\n'), Destination::Stdout))
+}
+
+/// The name of the option `arg`, if it is one, with `-n` and `-t` kept as
+/// short forms of `-name` and `-type`.
+pub fn option_name(arg: &str) -> Option<&str> {
+    match arg {
+        "-n" => Some("name"),
+        "-t" => Some("type"),
+        _ => arg
+            .strip_prefix("--")
+            .or_else(|| arg.strip_prefix('-'))
+            .filter(|name| !name.is_empty()),
+    }
+}
+
+struct Parser<'a, I: Iterator<Item = &'a str>> {
+    args: Peekable<I>,
+    now: SystemTime,
+    options: Vec<String>,
+}
+
+impl<'a, I: Iterator<Item = &'a str>> Parser<'a, I> {
+    /// Like find's -regextype, `--name-regex` applies to the tests after it.
+    fn name_regex(&self) -> bool {
+        self.options.iter().any(|option| option == "--name-regex")
+    }
+
+    fn eat(&mut self, operators: &[&str]) -> bool {
+        self.args.next_if(|arg| operators.contains(arg)).is_some()
+    }
+
+    fn arg(&mut self, name: &str) -> MyResult<&'a str> {
+        self.args
+            .next()
+            .ok_or_else(|| From::from(format!("Missing argument to --{}", name)))
+    }
+
+    fn parse_or(&mut self) -> MyResult<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.eat(&["-o", "-or", "--or"]) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> MyResult<Expr> {
+        let mut expr = self.parse_not()?;
+        loop {
+            // Adjacent expressions are implicitly joined by -a
+            let explicit = self.eat(&["-a", "-and", "--and"]);
+            let next = self.args.peek();
+            if !explicit && next.is_none_or(|arg| [")", "-o", "-or", "--or"].contains(arg)) {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+    }
+
+    fn parse_not(&mut self) -> MyResult<Expr> {
+        match self.eat(&["!", "-not", "--not"]) {
+            true => Ok(Expr::Not(Box::new(self.parse_not()?))),
+            false => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> MyResult<Expr> {
+        let arg = self
+            .args
+            .next()
+            .ok_or("Expected an expression at the end")?;
+        if arg == "(" {
+            let expr = self.parse_or()?;
+            return match self.args.next() {
+                Some(")") => Ok(expr),
+                _ => Err(From::from("Missing \")\" in expression")),
+            };
+        }
+
+        if let Some((flag, takes_value)) = crate::global_flag(arg) {
+            if takes_value {
+                let value = self
+                    .args
+                    .next()
+                    .ok_or(format!("Missing argument to {}", flag))?;
+                self.options.extend([flag, value.to_string()]);
+            } else {
+                self.options.push(flag);
+            }
+            return Ok(Expr::Predicate(Predicate::True));
+        }
+        let name = option_name(arg).ok_or_else(|| match arg {
+            ")" => format!("Expected an expression before \"{}\"", arg),
+            _ => format!("Paths must precede the expression: \"{}\"", arg),
+        })?;
+        let name_regex = self.name_regex();
+        match name {
+            "print" => Ok(print()),
+            "print0" => Ok(Expr::Action(Action::Print(
+                Format::path(0),
+                Destination::Stdout,
+            ))),
+            "printf" => {
+                let format = Format::parse(self.arg(name)?)?;
+                Ok(Expr::Action(Action::Print(format, Destination::Stdout)))
+            }
+            "fprint" | "fprint0" | "fprintf" => {
+                let filename = self.arg(name)?;
+                let format = match name {
+                    "fprint" => Format::path(b'\n'),
+                    "fprint0" => Format::path(0),
+                    _ => Format::parse(self.arg(name)?)?,
+                };
+                let file = File::create(filename).map_err(|e| format!("{}: {}", filename, e))?;
+                let destination = Destination::File(Mutex::new(BufWriter::new(file)));
+                Ok(Expr::Action(Action::Print(format, destination)))
+            }
+            "prune" => Ok(Expr::Action(Action::Prune)),
+            "delete" => Ok(Expr::Action(Action::Delete)),
+            "exec" | "execdir" | "ok" => Ok(Expr::Action(Action::Exec(Exec::parse(
+                name,
+                &mut self.args,
+            )?))),
+            _ => match Predicate::parse(name, &mut self.args, self.now, name_regex) {
+                Some(predicate) => Ok(Expr::Predicate(predicate?)),
+                None => Err(From::from(format!("Unknown predicate \"{}\"", arg))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Expr};
+    use std::time::SystemTime;
+
+    /// Renders the parsed tree in prefix form to check the grouping.
+    fn render(expr: &Expr) -> String {
+        match expr {
+            Expr::And(lhs, rhs) => format!("(and {} {})", render(lhs), render(rhs)),
+            Expr::Or(lhs, rhs) => format!("(or {} {})", render(lhs), render(rhs)),
+            Expr::Not(expr) => format!("(not {})", render(expr)),
+            Expr::Predicate(predicate) => format!("{:?}", predicate)
+                .split('(')
+                .next()
+                .unwrap()
+                .to_lowercase(),
+            Expr::Action(action) => format!("{:?}", action)
+                .split('(')
+                .next()
+                .unwrap()
+                .to_lowercase(),
+        }
+    }
+
+    fn parse_str(args: &str) -> Result<String, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        parse(&args, vec![], SystemTime::now())
+            .map(|(expr, _)| render(&expr))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(parse_str(""), Ok("print".to_string()));
+        assert_eq!(
+            parse_str("-name a -o -name b -type f"),
+            Ok("(and (or name (and name type)) print)".to_string())
+        );
+        assert_eq!(
+            parse_str("( -name a -o -name b ) -a ! -empty"),
+            Ok("(and (and (or name name) (not empty)) print)".to_string())
+        );
+        assert_eq!(
+            parse_str("-not -not -true -print -o -false"),
+            Ok("(or (and (not (not true)) print) false)".to_string())
+        );
+        assert_eq!(
+            parse_str("-name a -prune -o -print"),
+            Ok("(or (and name prune) print)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_global_options() {
+        let args: Vec<String> = ["-maxdepth", "1", "-type", "d", "--xdev"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let (expr, options) = parse(&args, vec![], SystemTime::now()).unwrap();
+        assert_eq!(render(&expr), "(and (and (and true type) true) print)");
+        assert_eq!(options, ["--maxdepth", "1", "--xdev"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |args| parse_str(args).unwrap_err();
+        assert_eq!(err("( -true"), "Missing \")\" in expression");
+        assert_eq!(err("-true )"), "Unexpected \")\" in expression");
+        assert_eq!(err("( )"), "Expected an expression before \")\"");
+        assert_eq!(err("!"), "Expected an expression at the end");
+        assert_eq!(
+            err("-true foo"),
+            "Paths must precede the expression: \"foo\""
+        );
+        assert_eq!(err("-bogus"), "Unknown predicate \"-bogus\"");
+        assert_eq!(err("-name"), "Missing argument to --name");
+        assert_eq!(err("-type x"), "Invalid --type \"x\"");
+    }
+}
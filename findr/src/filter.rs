@@ -0,0 +1,196 @@
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// The ignore files of a directory, most important first.
+const IGNORE_FILES: &[&str] = &[".ignore", ".gitignore", ".git/info/exclude"];
+
+/// Leaves entries out of the walk, along with everything below them. With
+/// `--respect-ignore` these are hidden entries, unless `--hidden` is
+/// given, and those excluded by ignore files.
+#[derive(Debug)]
+pub struct Filter {
+    respect_ignore: bool,
+    hidden: bool,
+    /// Git's global excludes file
+    global: Gitignore,
+    /// The rules for each directory walked so far
+    dirs: Mutex<HashMap<PathBuf, Arc<Rules>>>,
+}
+
+/// The ignore files that apply below a directory, its own and those of
+/// the directories above it.
+#[derive(Debug)]
+struct Rules {
+    /// The directory as walked, or for one above a starting point, the
+    /// starting point
+    dir: PathBuf,
+    /// Makes a path below `dir` relative to the ignore files
+    prefix: PathBuf,
+    ignores: Vec<Gitignore>,
+    parent: Option<Arc<Rules>>,
+}
+
+impl Filter {
+    pub fn new(respect_ignore: bool, hidden: bool) -> Filter {
+        let global = match respect_ignore {
+            true => {
+                let (global, error) = GitignoreBuilder::new("").build_global();
+                if let Some(e) = error {
+                    eprintln!("{}", e);
+                }
+                global
+            }
+            false => Gitignore::empty(),
+        };
+        Filter {
+            respect_ignore,
+            hidden,
+            global,
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether to leave out the entry at `path`, `depth` levels below its
+    /// starting point. Starting points are never left out.
+    pub fn skips(&self, path: &Path, is_dir: bool, depth: usize) -> bool {
+        if !self.respect_ignore || depth == 0 {
+            return false;
+        }
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."));
+        if hidden && !self.hidden {
+            return true;
+        }
+
+        let Some(dir) = path.parent() else {
+            return false;
+        };
+        // The closest ignore file that mentions the path decides
+        let mut rules = Some(self.rules(dir, depth - 1));
+        let mut relative = PathBuf::new();
+        while let Some(dir_rules) = rules {
+            relative = dir_rules
+                .prefix
+                .join(path.strip_prefix(&dir_rules.dir).unwrap_or(path));
+            for ignore in &dir_rules.ignores {
+                match ignore.matched_path_or_any_parents(&relative, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            rules = dir_rules.parent.clone();
+        }
+        self.global
+            .matched_path_or_any_parents(&relative, is_dir)
+            .is_ignore()
+    }
+
+    fn rules(&self, dir: &Path, depth: usize) -> Arc<Rules> {
+        if let Some(rules) = self.dirs.lock().unwrap().get(dir) {
+            return rules.clone();
+        }
+        let parent = match (depth, dir.parent()) {
+            (0, _) | (_, None) => outer_rules(dir),
+            (_, Some(parent)) => Some(self.rules(parent, depth - 1)),
+        };
+        let rules = Arc::new(Rules {
+            dir: dir.to_path_buf(),
+            prefix: PathBuf::new(),
+            ignores: read_ignores(dir),
+            parent,
+        });
+        self.dirs
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), rules.clone());
+        rules
+    }
+}
+
+/// The rules from the directories above the starting point `root`, up to
+/// the top of the git repository it's in, if it's in one.
+fn outer_rules(root: &Path) -> Option<Arc<Rules>> {
+    let absolute = fs::canonicalize(root).ok()?;
+    let top = absolute.ancestors().find(|dir| dir.join(".git").exists())?;
+    let above: Vec<&Path> = absolute
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(top))
+        .collect();
+    above.into_iter().rev().fold(None, |parent, dir| {
+        Some(Arc::new(Rules {
+            dir: root.to_path_buf(),
+            prefix: absolute.strip_prefix(dir).unwrap().to_path_buf(),
+            ignores: read_ignores(dir),
+            parent,
+        }))
+    })
+}
+
+fn read_ignores(dir: &Path) -> Vec<Gitignore> {
+    let mut ignores = vec![];
+    for name in IGNORE_FILES {
+        let path = dir.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        // Paths are made relative before they're matched
+        let mut builder = GitignoreBuilder::new("");
+        if let Some(e) = builder.add(&path) {
+            eprintln!("{}", e);
+        }
+        match builder.build() {
+            Ok(ignore) => ignores.push(ignore),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    ignores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn test_skips() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".git/info")).unwrap();
+        fs::create_dir_all(root.join("src/gen")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n/target/\n!keep.log\n").unwrap();
+        fs::write(root.join(".git/info/exclude"), "*.tmp\n").unwrap();
+        fs::write(root.join("src/.ignore"), "gen\n").unwrap();
+        fs::write(root.join("src/.gitignore"), "!*.log\n").unwrap();
+
+        let filter = Filter::new(true, false);
+        let skips = |path: &str, is_dir| {
+            let depth = Path::new(path).components().count();
+            filter.skips(&root.join(path), is_dir, depth)
+        };
+        assert!(skips("a.log", false));
+        assert!(!skips("keep.log", false));
+        assert!(skips("target", true));
+        assert!(!skips("target", false));
+        assert!(!skips("src/target", true));
+        assert!(skips("x.tmp", false));
+        assert!(skips(".hidden", false));
+        assert!(skips("src/gen", true));
+        assert!(!skips("src/a.log", false));
+        assert!(!filter.skips(root, true, 0));
+
+        let filter = Filter::new(true, true);
+        assert!(!filter.skips(&root.join(".hidden"), false, 1));
+        assert!(!Filter::new(false, false).skips(&root.join("a.log"), false, 1));
+    }
+}
@@ -1,12 +1,32 @@
-use crate::EntryType::*;
-use clap::{App, Arg};
-use regex::Regex;
+use crate::{
+    duplicates::Duplicates,
+    expr::Expr,
+    filter::Filter,
+    predicate::Visit,
+    top::{SortKey, Top},
+    walk::{walk_parallel, Entry},
+};
+use clap::{App, Arg, ArgMatches};
 use std::{
+    env,
     error::Error,
-    fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::SystemTime,
 };
-use walkdir::{DirEntry, WalkDir};
+use walkdir::WalkDir;
+
+mod duplicates;
+mod exec;
+mod expr;
+mod filter;
+#[cfg(unix)]
+mod mode;
+mod predicate;
+mod printf;
+mod top;
+mod walk;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
@@ -15,119 +35,461 @@ enum EntryType {
     Dir,
     File,
     Link,
+    Socket,
+    Fifo,
+    BlockDevice,
+    CharDevice,
 }
 
 #[derive(Debug)]
 pub struct Config {
     paths: Vec<String>,
-    names: Vec<Regex>,
-    entry_types: Vec<EntryType>,
+    expr: Expr,
+    min_depth: Option<usize>,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    same_file_system: bool,
+    contents_first: bool,
+    threads: usize,
+    sort: bool,
+    filter: Filter,
+    report: Option<Report>,
 }
 
+/// What is printed in place of the expression's output, once the walk is
+/// over.
+#[derive(Debug)]
+enum Report {
+    Duplicates(Duplicates),
+    Top(Top),
+}
+
+/// Options that apply to the whole search rather than to each entry, and
+/// whether they take a value. Like find, they may be given among the
+/// expression as well as before the paths.
+const GLOBAL_OPTIONS: &[(&str, bool)] = &[
+    ("L", false),
+    ("maxdepth", true),
+    ("mindepth", true),
+    ("xdev", false),
+    ("depth", false),
+    ("name-regex", false),
+    ("threads", true),
+    ("sort", false),
+    ("respect-ignore", false),
+    ("hidden", false),
+    ("duplicates", false),
+    ("min-size", true),
+    ("top", true),
+    ("by", true),
+    ("reverse", false),
+    ("mount", false),
+    ("h", false),
+    ("help", false),
+    ("V", false),
+    ("version", false),
+];
+
+const EXPRESSION_HELP: &str = "\
+EXPRESSION:
+    Entries are printed when the expression is true, which it is for all
+    entries when none is given. Tests are joined with -a (also implied
+    between adjacent tests), -o and ! (or -not), and grouped with ( ).
+    Options may be written with one dash or two.
+
+    -name PATTERN     File name matches the shell PATTERN
+    -iname PATTERN    Like -name, but ignoring case
+    -path PATTERN     Path matches the shell PATTERN, where * and ? also
+                      match /
+    -ipath PATTERN    Like -path, but ignoring case
+    -regex REGEX      REGEX matches the whole path
+    -iregex REGEX     Like -regex, but ignoring case
+    -type f|d|l|s|p|b|c
+                      Entry type, or a comma-separated list of types: file,
+                      directory, link, socket, FIFO, block or character
+                      device
+    -size [+-]N[cwbkMG]
+                      Size in units rounded up (default 512-byte blocks)
+    -atime, -ctime, -mtime [+-]N
+                      Last accessed, changed or modified N days ago
+    -amin, -cmin, -mmin [+-]N
+                      Last accessed, changed or modified N minutes ago
+    -newer FILE       Modified more recently than FILE
+    -empty            Empty file or directory
+    -user NAME        Owned by the user NAME or with the user ID NAME
+    -group NAME       Owned by the group NAME or with the group ID NAME
+    -nouser, -nogroup Owned by an unknown user or group
+    -perm MODE        Permissions are exactly MODE, in octal or symbolic
+                      form like u=rw,go=r
+    -perm -MODE       All of the permission bits in MODE are set
+    -perm /MODE       Any of the permission bits in MODE are set
+    -readable, -writable, -executable
+                      The current user can read, write or execute the entry
+    -true, -false     Always true or false
+    -print            Print the path followed by a newline
+    -print0           Print the path followed by a NUL byte
+    -printf FORMAT    Print FORMAT, where \\n, \\t, \\0 and \\NNN are escapes
+                      and these directives may have a width and
+                      precision, like %-10.5p:
+                        %p path             %f name without directories
+                        %P path below the starting point
+                        %h directories      %H starting point
+                        %s size in bytes    %k size in 1K blocks
+                        %m octal mode       %M mode as in ls -l
+                        %u user             %U user ID
+                        %g group            %G group ID
+                        %i inode            %n number of hard links
+                        %y type letter      %d depth
+                        %Tk, %Ak, %Ck modification, access or status
+                          change time, where k is a strftime conversion
+                          like Y, m or d, or @ for seconds since 1970
+                        %% a percent sign
+    -fprint FILE, -fprint0 FILE, -fprintf FILE FORMAT
+                      Like -print, -print0 and -printf, but write to FILE
+    -prune            Don't descend into the directory
+    -delete           Delete the file or empty directory (implies -depth)
+    -exec COMMAND ;   Run COMMAND with {} replaced by the path, true if it
+                      succeeds
+    -exec COMMAND {} +
+                      Run COMMAND with as many paths as fit at once
+    -execdir COMMAND ; and -execdir COMMAND {} +
+                      Like -exec, but run from the entry's directory
+    -ok COMMAND ;     Like -exec, but ask first";
+
 pub fn get_args() -> MyResult<Config> {
+    let args = env::args_os()
+        .skip(1)
+        .map(|arg| {
+            arg.into_string().map_err(|arg| {
+                From::from(format!("Invalid argument \"{}\"", arg.to_string_lossy()))
+            })
+        })
+        .collect::<MyResult<_>>()?;
+    parse_args(args)
+}
+
+/// Builds the configuration from a command line without the program
+/// name.
+pub fn parse_args(args: Vec<String>) -> MyResult<Config> {
+    let (options, paths, expr_args) = split_args(&args);
+    let (expr, options) = expr::parse(expr_args, options, SystemTime::now())?;
+
     let path_args = Arg::with_name("paths")
         .value_name("PATH")
         .help("Search paths")
         .default_value(".")
         .multiple(true);
-    let name_args = Arg::with_name("names")
-        .value_name("NAME")
-        .help("Name")
-        .short("n")
-        .takes_value(true)
-        .long("name")
-        .multiple(true);
-    let entry_arg = Arg::with_name("types")
-        .value_name("TYPE")
-        .short("t")
-        .long("type")
-        .help("Entry Type")
-        .possible_values(&["f", "d", "l"])
-        .multiple(true)
+    let max_depth_arg = Arg::with_name("max_depth")
+        .value_name("LEVELS")
+        .long("maxdepth")
+        .help("Descend at most LEVELS below the starting points")
         .takes_value(true);
+    let min_depth_arg = Arg::with_name("min_depth")
+        .value_name("LEVELS")
+        .long("mindepth")
+        .help("Ignore entries less than LEVELS below the starting points")
+        .takes_value(true);
+    let follow_arg = Arg::with_name("follow_links")
+        .short("L")
+        .help("Follow symbolic links");
+    let xdev_arg = Arg::with_name("same_file_system")
+        .long("xdev")
+        .alias("mount")
+        .help("Don't descend into other filesystems");
+    let name_regex_arg = Arg::with_name("name_regex")
+        .long("name-regex")
+        .help("Match -name against part of the file name as a regular expression");
+    let depth_arg = Arg::with_name("depth")
+        .long("depth")
+        .help("Visit a directory's contents before the directory");
+    let threads_arg = Arg::with_name("threads")
+        .value_name("N")
+        .long("threads")
+        .help("Walk with N threads, or one per CPU if N is 0")
+        .default_value("1");
+    let sort_arg = Arg::with_name("sort")
+        .long("sort")
+        .help("Walk each directory in order of file name");
+    let respect_ignore_arg = Arg::with_name("respect_ignore")
+        .long("respect-ignore")
+        .help(
+            "Skip hidden entries and those excluded by .gitignore, .ignore, \
+             .git/info/exclude or git's global excludes file",
+        );
+    let hidden_arg = Arg::with_name("hidden")
+        .long("hidden")
+        .help("Don't skip hidden entries with --respect-ignore");
+    let duplicates_arg = Arg::with_name("duplicates")
+        .long("duplicates")
+        .help("Print groups of files the expression is true for with the same contents")
+        .conflicts_with("top");
+    let min_size_arg = Arg::with_name("min_size")
+        .value_name("SIZE")
+        .long("min-size")
+        .help("Only compare files of at least SIZE bytes, or of -size units like 10M")
+        .default_value("1");
+    let top_arg = Arg::with_name("top")
+        .value_name("N")
+        .long("top")
+        .help("Print the first N entries the expression is true for by the --by key");
+    let by_arg = Arg::with_name("by")
+        .value_name("KEY")
+        .long("by")
+        .help("Rank --top entries by size, mtime or atime, largest or newest first")
+        .default_value("size");
+    let reverse_arg = Arg::with_name("reverse")
+        .long("reverse")
+        .help("Rank --top entries smallest or oldest first")
+        .requires("top");
 
     let matches = App::new("findr")
         .version("0.1.0")
         .author("Celal Taş <celal.tas123@gmail.com>")
         .about("Rust find")
+        .usage("findr [OPTIONS] [PATH]... [EXPRESSION]")
+        .after_help(EXPRESSION_HELP)
         .arg(path_args)
-        .arg(name_args)
-        .arg(entry_arg)
-        .get_matches();
-
-    let names = matches
-        .values_of_lossy("names")
-        .map(|val| {
-            val.into_iter()
-                .map(|name| Regex::new(&name).map_err(|_| format!("Invalid --name \"{}\"", name)))
-                .collect::<Result<Vec<_>, _>>()
-        })
-        .transpose()?
-        .unwrap_or_default();
-    let entry_types = matches
-        .values_of_lossy("types")
-        .map(|vals| {
-            vals.into_iter()
-                .map(|val| match val.as_str() {
-                    "d" => Dir,
-                    "f" => File,
-                    "l" => Link,
-                    _ => unreachable!("Invalid type"),
-                })
-                .collect()
-        })
-        .unwrap_or_default();
+        .arg(max_depth_arg)
+        .arg(min_depth_arg)
+        .arg(follow_arg)
+        .arg(xdev_arg)
+        .arg(depth_arg)
+        .arg(name_regex_arg)
+        .arg(threads_arg)
+        .arg(sort_arg)
+        .arg(respect_ignore_arg)
+        .arg(hidden_arg)
+        .arg(duplicates_arg)
+        .arg(min_size_arg)
+        .arg(top_arg)
+        .arg(by_arg)
+        .arg(reverse_arg)
+        .get_matches_from(
+            ["findr".to_string()]
+                .into_iter()
+                .chain(options)
+                .chain(paths.iter().cloned()),
+        );
 
     Ok(Config {
         paths: matches.values_of_lossy("paths").unwrap(),
-        names: names,
-        entry_types: entry_types,
+        min_depth: parse_count(matches.value_of("min_depth"), "mindepth")?,
+        max_depth: parse_count(matches.value_of("max_depth"), "maxdepth")?,
+        follow_links: matches.is_present("follow_links"),
+        same_file_system: matches.is_present("same_file_system"),
+        contents_first: matches.is_present("depth") || expr.deletes(),
+        threads: parse_count(matches.value_of("threads"), "threads")?.unwrap(),
+        sort: matches.is_present("sort"),
+        filter: Filter::new(
+            matches.is_present("respect_ignore"),
+            matches.is_present("hidden"),
+        ),
+        report: parse_report(&matches)?,
+        expr,
     })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let type_filter = |entry: &walkdir::DirEntry| {
-        config.entry_types.is_empty()
-            || config
-                .entry_types
-                .iter()
-                .any(|entry_type| match entry_type {
-                    Link => entry.file_type().is_symlink(),
-                    Dir => entry.file_type().is_dir(),
-                    File => entry.file_type().is_file(),
-                })
-    };
+fn parse_report(matches: &ArgMatches) -> MyResult<Option<Report>> {
+    if matches.is_present("duplicates") {
+        let min_size = duplicates::parse_min_size(matches.value_of("min_size").unwrap())?;
+        return Ok(Some(Report::Duplicates(Duplicates::new(min_size))));
+    }
+    match parse_count(matches.value_of("top"), "top")? {
+        Some(count) => {
+            let key = SortKey::parse(matches.value_of("by").unwrap())?;
+            let top = Top::new(count, key, matches.is_present("reverse"));
+            Ok(Some(Report::Top(top)))
+        }
+        None => Ok(None),
+    }
+}
+
+impl Config {
+    /// Evaluates the expression for an entry, adding it to the report if
+    /// it's true.
+    fn eval(&self, visit: &mut Visit, out: &mut dyn Write) -> io::Result<()> {
+        if self.expr.eval(visit, out)? {
+            match &self.report {
+                Some(Report::Duplicates(duplicates)) => duplicates.add(visit),
+                Some(Report::Top(top)) => top.add(visit),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
 
-    let name_filter = |entry: &walkdir::DirEntry| {
-        config.names.is_empty()
-            || config
-                .names
-                .iter()
-                .any(|re| re.is_match(&entry.file_name().to_string_lossy()))
+/// The flag clap knows the global option `arg` as, if it is one, and
+/// whether it takes a value.
+fn global_flag(arg: &str) -> Option<(String, bool)> {
+    let name = expr::option_name(arg)?;
+    let (_, takes_value) = GLOBAL_OPTIONS.iter().find(|(option, _)| *option == name)?;
+    let flag = match name.len() {
+        1 => format!("-{}", name),
+        _ => format!("--{}", name),
     };
+    Some((flag, *takes_value))
+}
 
-    for path in &config.paths {
-        let entries = WalkDir::new(path)
-            .into_iter()
-            .filter_map(|f| match f {
-                Err(e) => {
-                    eprintln!("{}", e);
-                    None
+/// Splits the command line into the global options before the paths, the
+/// paths and the expression, which starts at the first argument that
+/// looks like an option or is `(` or `!`.
+fn split_args(args: &[String]) -> (Vec<String>, &[String], &[String]) {
+    let mut options = vec![];
+    let mut i = 0;
+    while let Some((flag, takes_value)) = args.get(i).and_then(|arg| global_flag(arg)) {
+        options.push(flag);
+        if takes_value {
+            options.extend(args.get(i + 1).cloned());
+            i += 1;
+        }
+        i += 1;
+    }
+    let rest = args.get(i..).unwrap_or_default();
+    let paths_len = rest
+        .iter()
+        .position(|arg| arg == "(" || arg == "!" || (arg.starts_with('-') && arg.len() > 1))
+        .unwrap_or(rest.len());
+    (options, &rest[..paths_len], &rest[paths_len..])
+}
+
+fn parse_count(val: Option<&str>, name: &str) -> MyResult<Option<usize>> {
+    val.map(|v| {
+        v.parse()
+            .map_err(|_| From::from(format!("Invalid --{} \"{}\"", name, v)))
+    })
+    .transpose()
+}
+
+pub fn run(config: Config) -> MyResult<()> {
+    let mut stdout = BufWriter::new(io::stdout());
+    match &config.report {
+        None => walk_all(&config, &mut stdout)?,
+        Some(report) => {
+            walk_all(&config, io::sink())?;
+            match report {
+                Report::Duplicates(duplicates) => duplicates.print(&mut stdout)?,
+                Report::Top(top) => top.print(&mut stdout)?,
+            }
+            stdout.flush()?;
+        }
+    }
+    config.expr.finish()
+}
+
+/// Walks every starting point, writing what the expression prints to
+/// `out`.
+fn walk_all<W: Write + Send>(config: &Config, mut out: W) -> MyResult<()> {
+    // The parallel walk only knows which device a file is on under Unix,
+    // so elsewhere -xdev is left to WalkDir
+    let serial = config.threads == 1 || (config.same_file_system && cfg!(not(unix)));
+    match serial {
+        true => {
+            for path in &config.paths {
+                walk(config, path, &mut out)?;
+            }
+            out.flush()?;
+        }
+        false => {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(config.threads)
+                .build()?;
+            let out = Mutex::new(out);
+            pool.install(|| {
+                config
+                    .paths
+                    .iter()
+                    .try_for_each(|path| walk_parallel(config, path, &out))
+            })?;
+            out.into_inner().unwrap().flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Walks the tree under `path` on this thread.
+fn walk(config: &Config, path: &str, out: &mut impl Write) -> io::Result<()> {
+    let root = Path::new(path);
+    let mut walker = WalkDir::new(path)
+        .follow_links(config.follow_links)
+        .same_file_system(config.same_file_system);
+    if let Some(depth) = config.min_depth {
+        walker = walker.min_depth(depth);
+    }
+    if let Some(depth) = config.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    if config.sort {
+        walker = walker.sort_by_file_name();
+    }
+
+    // Skipped directories are never descended into. WalkDir can't skip a
+    // directory it yields after its contents, so -depth is handled here
+    // by holding directories back until the walk has left them.
+    let mut iter = walker.into_iter().filter_entry(|entry| {
+        !config
+            .filter
+            .skips(entry.path(), entry.file_type().is_dir(), entry.depth())
+    });
+    let mut deferred: Vec<Entry> = vec![];
+    while let Some(result) = iter.next() {
+        match result {
+            Err(e) => report_error(&e),
+            Ok(entry) => {
+                let entry = Entry::from(entry);
+                while let Some(dir) = deferred.pop_if(|dir| dir.depth() >= entry.depth()) {
+                    config.eval(&mut Visit::new(&dir, root), out)?;
                 }
-                Ok(entry) => Some(entry),
-            })
-            .filter(type_filter)
-            .filter(name_filter)
-            .map(|entry| entry.path().display().to_string())
-            .collect::<Vec<_>>();
-        println!("{}", entries.join("\n"));
+                if config.contents_first && entry.file_type().is_dir() {
+                    deferred.push(entry);
+                    continue;
+                }
+                let mut visit = Visit::new(&entry, root);
+                config.eval(&mut visit, out)?;
+                // Contents come first with -depth, so there's nothing
+                // left to prune
+                if visit.prune && entry.file_type().is_dir() && !config.contents_first {
+                    iter.skip_current_dir();
+                }
+            }
+        }
+    }
+    while let Some(dir) = deferred.pop() {
+        config.eval(&mut Visit::new(&dir, root), out)?;
     }
     Ok(())
 }
 
-fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+fn report_error(e: &walkdir::Error) {
+    match (e.path(), e.loop_ancestor()) {
+        (Some(path), Some(ancestor)) => eprintln!(
+            "File system loop detected; '{}' is part of the same file system loop as '{}'.",
+            path.display(),
+            ancestor.display()
+        ),
+        _ => eprintln!("{}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_args;
+
+    #[test]
+    fn test_split_args() {
+        let args: Vec<String> = ["-L", "-maxdepth", "2", "a", "b", "!", "-name", "x", "-xdev"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let (options, paths, expr) = split_args(&args);
+        assert_eq!(options, ["-L", "--maxdepth", "2"]);
+        assert_eq!(paths, ["a", "b"]);
+        assert_eq!(expr, ["!", "-name", "x", "-xdev"]);
+
+        let args = vec!["-".to_string(), "(".to_string()];
+        let (options, paths, expr) = split_args(&args);
+        assert!(options.is_empty());
+        assert_eq!(paths, ["-"]);
+        assert_eq!(expr, ["("]);
     }
 }
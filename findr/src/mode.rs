@@ -0,0 +1,118 @@
+/// Parses a permission mode given in octal, like `644`, or symbolically,
+/// like `u+w,g-x` or `a=rx`. Symbolic modes start from no permissions.
+pub fn parse_mode(val: &str) -> Option<u32> {
+    if !val.is_empty() && val.bytes().all(|b| b.is_ascii_digit()) {
+        return u32::from_str_radix(val, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777);
+    }
+    val.split(',').try_fold(0, apply_clause)
+}
+
+/// Applies one clause of a symbolic mode, like `ug+rw`, to `mode`.
+fn apply_clause(mut mode: u32, clause: &str) -> Option<u32> {
+    let op_start = clause.find(['+', '-', '='])?;
+    let (who, mut rest) = clause.split_at(op_start);
+    let mut who_mask = 0;
+    for c in who.chars() {
+        who_mask |= match c {
+            'u' => 0o4700,
+            'g' => 0o2070,
+            'o' => 0o1007,
+            'a' => 0o7777,
+            _ => return None,
+        };
+    }
+    if who_mask == 0 {
+        who_mask = 0o7777;
+    }
+
+    // Each clause may hold several operations, like `u+r-w`
+    while let Some(op) = rest.chars().next() {
+        let perms_end = rest[1..]
+            .find(['+', '-', '='])
+            .map_or(rest.len(), |i| i + 1);
+        let mut bits = 0;
+        for c in rest[1..perms_end].chars() {
+            bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' | 'X' => 0o111,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+        let bits = bits & who_mask;
+        match op {
+            '+' => mode |= bits,
+            '-' => mode &= !bits,
+            '=' => mode = (mode & !who_mask) | bits,
+            _ => return None,
+        }
+        rest = &rest[perms_end..];
+    }
+    Some(mode)
+}
+
+/// Formats `mode`, including the file type bits, like `ls -l` does, for
+/// example `drwxr-xr-x`.
+pub fn format_mode(mode: u32) -> String {
+    let file_type = match mode & libc::S_IFMT {
+        libc::S_IFDIR => 'd',
+        libc::S_IFLNK => 'l',
+        libc::S_IFSOCK => 's',
+        libc::S_IFIFO => 'p',
+        libc::S_IFBLK => 'b',
+        libc::S_IFCHR => 'c',
+        _ => '-',
+    };
+    let mut result = String::from(file_type);
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        result.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_mode, parse_mode};
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("644"), Some(0o644));
+        assert_eq!(parse_mode("4755"), Some(0o4755));
+        assert_eq!(parse_mode("0"), Some(0));
+        assert_eq!(parse_mode("u+w"), Some(0o200));
+        assert_eq!(parse_mode("u=rw,go=r"), Some(0o644));
+        assert_eq!(parse_mode("a+x"), Some(0o111));
+        assert_eq!(parse_mode("+r"), Some(0o444));
+        assert_eq!(parse_mode("u+rwx-w"), Some(0o500));
+        assert_eq!(parse_mode("ug+s,o+t"), Some(0o7000));
+        assert_eq!(parse_mode("u+w,g-x"), Some(0o200));
+        assert_eq!(parse_mode("888"), None);
+        assert_eq!(parse_mode("17777"), None);
+        assert_eq!(parse_mode("u"), None);
+        assert_eq!(parse_mode("z+w"), None);
+        assert_eq!(parse_mode("u+q"), None);
+        assert_eq!(parse_mode(""), None);
+    }
+
+    #[test]
+    fn test_format_mode() {
+        assert_eq!(format_mode(0o100644), "-rw-r--r--");
+        assert_eq!(format_mode(0o040755), "drwxr-xr-x");
+        assert_eq!(format_mode(0o120777), "lrwxrwxrwx");
+        assert_eq!(format_mode(0o104755), "-rwsr-xr-x");
+        assert_eq!(format_mode(0o041777), "drwxrwxrwt");
+        assert_eq!(format_mode(0o102640), "-rw-r-S---");
+    }
+}
@@ -0,0 +1,488 @@
+use crate::{
+    walk::{report_io_error, Entry},
+    EntryType, MyResult,
+};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use std::{
+    fs::{self, FileType, Metadata},
+    path::Path,
+    time::SystemTime,
+};
+#[cfg(unix)]
+use {
+    crate::mode::parse_mode,
+    std::{
+        ffi::CString,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::{FileTypeExt, MetadataExt},
+        },
+        time::Duration,
+    },
+};
+
+const MINUTE: u64 = 60;
+const DAY: u64 = 24 * 60 * MINUTE;
+
+/// A numeric argument as in find: `+N` for more than N, `-N` for less
+/// than N and `N` for exactly N.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Cmp {
+    Less(u64),
+    Exactly(u64),
+    More(u64),
+}
+
+impl Cmp {
+    fn matches(&self, value: u64) -> bool {
+        match *self {
+            Cmp::Less(n) => value < n,
+            Cmp::Exactly(n) => value == n,
+            Cmp::More(n) => value > n,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum TimeField {
+    Accessed,
+    #[cfg(unix)]
+    Changed,
+    Modified,
+}
+
+/// A leaf of the expression, evaluated against a single entry. Owners,
+/// permissions and change times are only known on Unix, and elsewhere the
+/// predicates that test them are rejected.
+#[derive(Debug)]
+pub enum Predicate {
+    True,
+    False,
+    /// File name
+    Name(Matcher),
+    /// Whole path, starting with the search path it was found under
+    Path(Matcher),
+    Type(Vec<EntryType>),
+    /// Size rounded up to a whole number of units of the given bytes
+    Size(Cmp, u64),
+    /// Age relative to the start of the search in whole periods of the
+    /// given seconds
+    Time(TimeField, Cmp, u64, SystemTime),
+    Newer(SystemTime),
+    Empty,
+    #[cfg(unix)]
+    User(u32),
+    #[cfg(unix)]
+    Group(u32),
+    #[cfg(unix)]
+    NoUser,
+    #[cfg(unix)]
+    NoGroup,
+    #[cfg(unix)]
+    Perm(PermMatch, u32),
+    /// Accessible with the given `access(2)` mode
+    #[cfg(unix)]
+    Access(i32),
+}
+
+/// How `-perm` compares the permission bits with its mode.
+#[cfg(unix)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum PermMatch {
+    Exactly,
+    /// `-MODE`
+    All,
+    /// `/MODE`
+    Any,
+}
+
+#[derive(Debug)]
+pub enum Matcher {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// A shell pattern where `*`, `?` and `[...]` also match `/`, as in
+    /// find.
+    fn glob(pattern: &str, case_insensitive: bool) -> Option<Matcher> {
+        GlobBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .literal_separator(false)
+            .backslash_escape(true)
+            .build()
+            .ok()
+            .map(|glob| Matcher::Glob(glob.compile_matcher()))
+    }
+
+    /// A regular expression that has to match the whole text unless
+    /// `partial` is set.
+    fn regex(pattern: &str, case_insensitive: bool, partial: bool) -> Option<Matcher> {
+        let pattern = match partial {
+            true => pattern.to_string(),
+            false => format!("^(?:{})$", pattern),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .ok()
+            .map(Matcher::Regex)
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Glob(glob) => glob.is_match(text),
+            Matcher::Regex(re) => re.is_match(text),
+        }
+    }
+}
+
+/// The entry an expression is being evaluated against, with its metadata
+/// looked up at most once.
+pub struct Visit<'a> {
+    pub entry: &'a Entry,
+    /// The starting point the entry was found under
+    pub root: &'a Path,
+    pub prune: bool,
+    metadata: Option<Option<Metadata>>,
+}
+
+impl<'a> Visit<'a> {
+    pub fn new(entry: &'a Entry, root: &'a Path) -> Visit<'a> {
+        Visit {
+            entry,
+            root,
+            prune: false,
+            metadata: None,
+        }
+    }
+
+    pub fn metadata(&mut self) -> Option<&Metadata> {
+        let entry = self.entry;
+        self.metadata
+            .get_or_insert_with(|| match entry.metadata() {
+                Ok(metadata) => Some(metadata),
+                Err(e) => {
+                    report_io_error(entry.path(), &e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+impl Predicate {
+    /// Parses the predicate `name` given its arguments, or returns `None`
+    /// if there's no such predicate. `args` is advanced past the ones used.
+    /// With `name_regex`, `-name` takes an unanchored regular expression
+    /// rather than a shell pattern.
+    pub fn parse<'a>(
+        name: &str,
+        args: &mut impl Iterator<Item = &'a str>,
+        now: SystemTime,
+        name_regex: bool,
+    ) -> Option<MyResult<Predicate>> {
+        let mut arg = || {
+            args.next()
+                .ok_or_else(|| From::from(format!("Missing argument to --{}", name)))
+        };
+        let invalid = |val: &str| format!("Invalid --{} \"{}\"", name, val);
+        let matcher = |make: fn(Matcher) -> Predicate, matcher: Option<Matcher>, val: &str| {
+            matcher.map(make).ok_or_else(|| From::from(invalid(val)))
+        };
+        let time = |field, period, val: &str| match parse_cmp(val) {
+            Some(cmp) => Ok(Predicate::Time(field, cmp, period, now)),
+            None => Err(From::from(invalid(val))),
+        };
+
+        let predicate = match name {
+            "true" => Ok(Predicate::True),
+            "false" => Ok(Predicate::False),
+            "name" if name_regex => arg()
+                .and_then(|val| matcher(Predicate::Name, Matcher::regex(val, false, true), val)),
+            "name" | "iname" => arg()
+                .and_then(|val| matcher(Predicate::Name, Matcher::glob(val, name == "iname"), val)),
+            "path" | "ipath" => arg()
+                .and_then(|val| matcher(Predicate::Path, Matcher::glob(val, name == "ipath"), val)),
+            "regex" | "iregex" => arg().and_then(|val| {
+                matcher(
+                    Predicate::Path,
+                    Matcher::regex(val, name == "iregex", false),
+                    val,
+                )
+            }),
+            "type" => {
+                arg().and_then(|val| parse_types(val).ok_or_else(|| From::from(invalid(val))))
+            }
+            "size" => arg().and_then(parse_size),
+            "atime" => arg().and_then(|val| time(TimeField::Accessed, DAY, val)),
+            #[cfg(unix)]
+            "ctime" => arg().and_then(|val| time(TimeField::Changed, DAY, val)),
+            "mtime" => arg().and_then(|val| time(TimeField::Modified, DAY, val)),
+            "amin" => arg().and_then(|val| time(TimeField::Accessed, MINUTE, val)),
+            #[cfg(unix)]
+            "cmin" => arg().and_then(|val| time(TimeField::Changed, MINUTE, val)),
+            "mmin" => arg().and_then(|val| time(TimeField::Modified, MINUTE, val)),
+            "newer" => arg().and_then(|file| {
+                fs::metadata(file)
+                    .and_then(|meta| meta.modified())
+                    .map(Predicate::Newer)
+                    .map_err(|e| From::from(format!("{}: {}", file, e)))
+            }),
+            "empty" => Ok(Predicate::Empty),
+            #[cfg(unix)]
+            "user" => arg().and_then(|val| {
+                users::get_user_by_name(val)
+                    .map(|user| user.uid())
+                    .or_else(|| val.parse().ok())
+                    .map(Predicate::User)
+                    .ok_or_else(|| From::from(invalid(val)))
+            }),
+            #[cfg(unix)]
+            "group" => arg().and_then(|val| {
+                users::get_group_by_name(val)
+                    .map(|group| group.gid())
+                    .or_else(|| val.parse().ok())
+                    .map(Predicate::Group)
+                    .ok_or_else(|| From::from(invalid(val)))
+            }),
+            #[cfg(unix)]
+            "nouser" => Ok(Predicate::NoUser),
+            #[cfg(unix)]
+            "nogroup" => Ok(Predicate::NoGroup),
+            #[cfg(unix)]
+            "perm" => arg().and_then(|val| {
+                let (how, mode) = match val.as_bytes().first() {
+                    Some(b'-') => (PermMatch::All, &val[1..]),
+                    Some(b'/') => (PermMatch::Any, &val[1..]),
+                    _ => (PermMatch::Exactly, val),
+                };
+                parse_mode(mode)
+                    .map(|mode| Predicate::Perm(how, mode))
+                    .ok_or_else(|| From::from(invalid(val)))
+            }),
+            #[cfg(unix)]
+            "readable" => Ok(Predicate::Access(libc::R_OK)),
+            #[cfg(unix)]
+            "writable" => Ok(Predicate::Access(libc::W_OK)),
+            #[cfg(unix)]
+            "executable" => Ok(Predicate::Access(libc::X_OK)),
+            #[cfg(not(unix))]
+            "ctime" | "cmin" | "user" | "group" | "nouser" | "nogroup" | "perm" | "readable"
+            | "writable" | "executable" => Err(From::from(format!(
+                "--{} is not supported on this platform",
+                name
+            ))),
+            _ => return None,
+        };
+        Some(predicate)
+    }
+
+    pub fn matches(&self, visit: &mut Visit) -> bool {
+        let entry = visit.entry;
+        match self {
+            Predicate::True => true,
+            Predicate::False => false,
+            Predicate::Name(matcher) => matcher.is_match(&entry.file_name().to_string_lossy()),
+            Predicate::Path(matcher) => matcher.is_match(&entry.path().to_string_lossy()),
+            Predicate::Type(types) => types.iter().any(|entry_type| match entry_type {
+                EntryType::Link => entry.file_type().is_symlink(),
+                EntryType::Dir => entry.file_type().is_dir(),
+                EntryType::File => entry.file_type().is_file(),
+                special => is_special(entry.file_type(), special),
+            }),
+            Predicate::Size(cmp, unit) => visit
+                .metadata()
+                .is_some_and(|metadata| cmp.matches(metadata.len().div_ceil(*unit))),
+            Predicate::Time(field, cmp, period, now) => visit
+                .metadata()
+                .and_then(|metadata| time_of(metadata, *field))
+                .is_some_and(|time| {
+                    // Times in the future count as no time ago
+                    let age = now.duration_since(time).unwrap_or_default();
+                    cmp.matches(age.as_secs() / period)
+                }),
+            Predicate::Newer(reference) => visit
+                .metadata()
+                .and_then(|metadata| metadata.modified().ok())
+                .is_some_and(|time| time > *reference),
+            Predicate::Empty => match visit.metadata() {
+                Some(metadata) if metadata.is_dir() => fs::read_dir(entry.path())
+                    .map(|mut entries| entries.next().is_none())
+                    .unwrap_or(false),
+                Some(metadata) => metadata.is_file() && metadata.len() == 0,
+                None => false,
+            },
+            #[cfg(unix)]
+            Predicate::User(uid) => visit.metadata().is_some_and(|m| m.uid() == *uid),
+            #[cfg(unix)]
+            Predicate::Group(gid) => visit.metadata().is_some_and(|m| m.gid() == *gid),
+            #[cfg(unix)]
+            Predicate::NoUser => visit
+                .metadata()
+                .is_some_and(|m| users::get_user_by_uid(m.uid()).is_none()),
+            #[cfg(unix)]
+            Predicate::NoGroup => visit
+                .metadata()
+                .is_some_and(|m| users::get_group_by_gid(m.gid()).is_none()),
+            #[cfg(unix)]
+            Predicate::Perm(how, mode) => visit.metadata().is_some_and(|metadata| {
+                let perms = metadata.mode() & 0o7777;
+                match how {
+                    PermMatch::Exactly => perms == *mode,
+                    PermMatch::All => perms & mode == *mode,
+                    // As in find, no bits at all match anything
+                    PermMatch::Any => *mode == 0 || perms & mode != 0,
+                }
+            }),
+            #[cfg(unix)]
+            Predicate::Access(how) => CString::new(entry.path().as_os_str().as_bytes())
+                // SAFETY: the path is a valid NUL-terminated string
+                .is_ok_and(|path| unsafe { libc::access(path.as_ptr(), *how) } == 0),
+        }
+    }
+}
+
+/// Whether `file_type` is a socket, FIFO or device, as `entry_type` asks.
+#[cfg(unix)]
+fn is_special(file_type: FileType, entry_type: &EntryType) -> bool {
+    match entry_type {
+        EntryType::Socket => file_type.is_socket(),
+        EntryType::Fifo => file_type.is_fifo(),
+        EntryType::BlockDevice => file_type.is_block_device(),
+        EntryType::CharDevice => file_type.is_char_device(),
+        _ => false,
+    }
+}
+
+/// Only Unix has sockets, FIFOs and devices in the file system.
+#[cfg(not(unix))]
+fn is_special(_file_type: FileType, _entry_type: &EntryType) -> bool {
+    false
+}
+
+/// Parses a comma-separated list of types like `f,l`.
+fn parse_types(val: &str) -> Option<Predicate> {
+    val.split(',')
+        .map(|entry_type| match entry_type {
+            "d" => Some(EntryType::Dir),
+            "f" => Some(EntryType::File),
+            "l" => Some(EntryType::Link),
+            "s" => Some(EntryType::Socket),
+            "p" => Some(EntryType::Fifo),
+            "b" => Some(EntryType::BlockDevice),
+            "c" => Some(EntryType::CharDevice),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(Predicate::Type)
+}
+
+fn parse_cmp(val: &str) -> Option<Cmp> {
+    let (make, num): (fn(u64) -> Cmp, &str) = match val.as_bytes().first() {
+        Some(b'+') => (Cmp::More, &val[1..]),
+        Some(b'-') => (Cmp::Less, &val[1..]),
+        _ => (Cmp::Exactly, val),
+    };
+    num.parse().ok().map(make)
+}
+
+fn parse_size(val: &str) -> MyResult<Predicate> {
+    let (num, unit) = match val.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&val[..i], c),
+        _ => (val, 'b'),
+    };
+    match (parse_cmp(num), size_unit(unit)) {
+        (Some(cmp), Some(unit)) => Ok(Predicate::Size(cmp, unit)),
+        _ => Err(From::from(format!("Invalid --size \"{}\"", val))),
+    }
+}
+
+/// The number of bytes in a unit of `-size`.
+pub fn size_unit(unit: char) -> Option<u64> {
+    match unit {
+        'c' => Some(1),
+        'w' => Some(2),
+        'b' => Some(512),
+        'k' => Some(1024),
+        'M' => Some(1024 * 1024),
+        'G' => Some(1024 * 1024 * 1024),
+        _ => None,
+    }
+}
+
+pub fn time_of(metadata: &Metadata, field: TimeField) -> Option<SystemTime> {
+    match field {
+        TimeField::Accessed => metadata.accessed().ok(),
+        TimeField::Modified => metadata.modified().ok(),
+        #[cfg(unix)]
+        TimeField::Changed => {
+            let ctime = Duration::new(metadata.ctime() as u64, metadata.ctime_nsec() as u32);
+            Some(SystemTime::UNIX_EPOCH + ctime)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cmp, parse_size, parse_types, Cmp, Matcher, Predicate};
+    use crate::EntryType;
+
+    #[test]
+    fn test_parse_cmp() {
+        assert_eq!(parse_cmp("3"), Some(Cmp::Exactly(3)));
+        assert_eq!(parse_cmp("+3"), Some(Cmp::More(3)));
+        assert_eq!(parse_cmp("-0"), Some(Cmp::Less(0)));
+        assert_eq!(parse_cmp(""), None);
+        assert_eq!(parse_cmp("+-3"), None);
+        assert_eq!(parse_cmp("x"), None);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        let size = |val| match parse_size(val) {
+            Ok(Predicate::Size(cmp, unit)) => Some((cmp, unit)),
+            _ => None,
+        };
+        assert_eq!(size("10"), Some((Cmp::Exactly(10), 512)));
+        assert_eq!(size("+1k"), Some((Cmp::More(1), 1024)));
+        assert_eq!(size("-2M"), Some((Cmp::Less(2), 1 << 20)));
+        assert_eq!(size("5c"), Some((Cmp::Exactly(5), 1)));
+        assert_eq!(
+            parse_size("1x").unwrap_err().to_string(),
+            "Invalid --size \"1x\""
+        );
+        assert!(parse_size("k").is_err());
+    }
+
+    #[test]
+    fn test_matcher() {
+        let glob = Matcher::glob("*.r[st]", false).unwrap();
+        assert!(glob.is_match("lib.rs"));
+        assert!(glob.is_match("a/b.rt"));
+        assert!(!glob.is_match("lib.rsx"));
+        assert!(!glob.is_match("LIB.RS"));
+        assert!(Matcher::glob("*.RS", true).unwrap().is_match("lib.rs"));
+        assert!(Matcher::glob("\\*", false).unwrap().is_match("*"));
+        assert!(Matcher::glob("[", false).is_none());
+
+        let re = Matcher::regex(".*/src/.*", false, false).unwrap();
+        assert!(re.is_match("./src/lib.rs"));
+        assert!(!re.is_match("src/lib.rs"));
+        let re = Matcher::regex("a|b", true, false).unwrap();
+        assert!(re.is_match("A"));
+        assert!(!re.is_match("ab"));
+        assert!(Matcher::regex("a|b", false, true).unwrap().is_match("cab"));
+    }
+
+    #[test]
+    fn test_parse_types() {
+        assert!(matches!(
+            parse_types("f,l"),
+            Some(Predicate::Type(types)) if types == [EntryType::File, EntryType::Link]
+        ));
+        assert!(parse_types("f,").is_none());
+        assert!(parse_types("x").is_none());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::{predicate::Visit, MyResult};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use std::{
+    fs::{FileType, Metadata},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+#[cfg(unix)]
+use {
+    crate::mode::format_mode,
+    std::os::unix::fs::{FileTypeExt, MetadataExt},
+};
+
+/// A `-printf` format, or the fixed one used by `-print` and `-print0`.
+#[derive(Debug)]
+pub struct Format(Vec<Piece>);
+
+#[derive(Debug)]
+enum Piece {
+    Literal(Vec<u8>),
+    Directive {
+        field: Field,
+        left_align: bool,
+        width: usize,
+        precision: Option<usize>,
+    },
+}
+
+/// A `%` directive. Blocks, modes, owners, inodes, links and change times
+/// are only known on Unix.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Field {
+    /// `%p`
+    Path,
+    /// `%P`, the path below the starting point
+    RelativePath,
+    /// `%H`
+    StartingPoint,
+    /// `%f`
+    Name,
+    /// `%h`
+    Dir,
+    /// `%s`
+    Size,
+    /// `%k`, in 1K blocks
+    #[cfg(unix)]
+    Blocks,
+    /// `%m`
+    #[cfg(unix)]
+    OctalMode,
+    /// `%M`
+    #[cfg(unix)]
+    SymbolicMode,
+    /// `%u`
+    #[cfg(unix)]
+    User,
+    /// `%U`
+    #[cfg(unix)]
+    Uid,
+    /// `%g`
+    #[cfg(unix)]
+    Group,
+    /// `%G`
+    #[cfg(unix)]
+    Gid,
+    /// `%i`
+    #[cfg(unix)]
+    Inode,
+    /// `%n`
+    #[cfg(unix)]
+    Links,
+    /// `%y`
+    Type,
+    /// `%d`
+    Depth,
+    /// `%A`, `%C` and `%T` followed by a `strftime` conversion, or `@` for
+    /// seconds since the epoch
+    Time(TimeKind, char),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TimeKind {
+    Accessed,
+    #[cfg(unix)]
+    Changed,
+    Modified,
+}
+
+impl Format {
+    /// The path followed by `terminator`, as printed by `-print`.
+    pub fn path(terminator: u8) -> Format {
+        Format(vec![
+            Piece::Directive {
+                field: Field::Path,
+                left_align: false,
+                width: 0,
+                precision: None,
+            },
+            Piece::Literal(vec![terminator]),
+        ])
+    }
+
+    /// Parses a format in the language of GNU find's `-printf`: `%`
+    /// directives with optional `-`, width and precision, and backslash
+    /// escapes. Unlike `-print`, no newline is added.
+    pub fn parse(format: &str) -> MyResult<Format> {
+        let mut pieces = vec![];
+        let mut literal = vec![];
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => unescape(&mut chars, &mut literal),
+                '%' if chars.peek() == Some(&'%') => {
+                    chars.next();
+                    literal.push(b'%');
+                }
+                '%' => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(parse_directive(&mut chars, format)?);
+                }
+                _ => literal.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Ok(Format(pieces))
+    }
+
+    /// Appends the formatted entry to `out`.
+    pub fn render(&self, visit: &mut Visit, out: &mut Vec<u8>) {
+        for piece in &self.0 {
+            match piece {
+                Piece::Literal(bytes) => out.extend(bytes),
+                Piece::Directive {
+                    field,
+                    left_align,
+                    width,
+                    precision,
+                } => {
+                    let mut value = field_value(*field, visit);
+                    if let Some(precision) = precision {
+                        value = truncate(value, *precision);
+                    }
+                    let padding = width.saturating_sub(char_count(&value));
+                    if !left_align {
+                        out.extend(std::iter::repeat_n(b' ', padding));
+                    }
+                    out.extend(&value);
+                    if *left_align {
+                        out.extend(std::iter::repeat_n(b' ', padding));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_directive(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    format: &str,
+) -> MyResult<Piece> {
+    let invalid = || format!("Invalid --printf format \"{}\"", format);
+    let left_align = chars.next_if_eq(&'-').is_some();
+    let mut width = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        width = width * 10 + digit as usize;
+        chars.next();
+    }
+    let mut precision = None;
+    if chars.next_if_eq(&'.').is_some() {
+        let mut value = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            value = value * 10 + digit as usize;
+            chars.next();
+        }
+        precision = Some(value);
+    }
+
+    let field = match chars.next().ok_or_else(invalid)? {
+        'p' => Field::Path,
+        'P' => Field::RelativePath,
+        'H' => Field::StartingPoint,
+        'f' => Field::Name,
+        'h' => Field::Dir,
+        's' => Field::Size,
+        #[cfg(unix)]
+        'k' => Field::Blocks,
+        #[cfg(unix)]
+        'm' => Field::OctalMode,
+        #[cfg(unix)]
+        'M' => Field::SymbolicMode,
+        #[cfg(unix)]
+        'u' => Field::User,
+        #[cfg(unix)]
+        'U' => Field::Uid,
+        #[cfg(unix)]
+        'g' => Field::Group,
+        #[cfg(unix)]
+        'G' => Field::Gid,
+        #[cfg(unix)]
+        'i' => Field::Inode,
+        #[cfg(unix)]
+        'n' => Field::Links,
+        #[cfg(not(unix))]
+        c @ ('k' | 'm' | 'M' | 'u' | 'U' | 'g' | 'G' | 'i' | 'n') => {
+            return Err(From::from(format!(
+                "%{} in --printf is not supported on this platform",
+                c
+            )))
+        }
+        'y' => Field::Type,
+        'd' => Field::Depth,
+        kind @ ('A' | 'C' | 'T') => {
+            let kind = match kind {
+                'A' => TimeKind::Accessed,
+                #[cfg(unix)]
+                'C' => TimeKind::Changed,
+                #[cfg(not(unix))]
+                'C' => {
+                    return Err(From::from(
+                        "%C in --printf is not supported on this platform",
+                    ))
+                }
+                _ => TimeKind::Modified,
+            };
+            let conversion = chars.next().ok_or_else(invalid)?;
+            let spec = format!("%{}", conversion);
+            let valid = conversion == '@'
+                || StrftimeItems::new(&spec).all(|item| !matches!(item, Item::Error));
+            if !valid {
+                return Err(From::from(invalid()));
+            }
+            Field::Time(kind, conversion)
+        }
+        _ => return Err(From::from(invalid())),
+    };
+    Ok(Piece::Directive {
+        field,
+        left_align,
+        width,
+        precision,
+    })
+}
+
+/// Appends the character escaped by a backslash, with `\NNN` taken as an
+/// octal byte.
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>, out: &mut Vec<u8>) {
+    let byte = match chars.next() {
+        Some('n') => b'\n',
+        Some('t') => b'\t',
+        Some('r') => b'\r',
+        Some('a') => 0x07,
+        Some('b') => 0x08,
+        Some('f') => 0x0c,
+        Some('v') => 0x0b,
+        Some('\\') => b'\\',
+        Some(c @ '0'..='7') => {
+            let mut value = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(digit) => {
+                        value = value * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            value as u8
+        }
+        Some(c) => {
+            out.push(b'\\');
+            out.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            return;
+        }
+        None => b'\\',
+    };
+    out.push(byte);
+}
+
+fn field_value(field: Field, visit: &mut Visit) -> Vec<u8> {
+    let entry = visit.entry;
+    let path = entry.path();
+    match field {
+        Field::Path => path.as_os_str().as_encoded_bytes().to_vec(),
+        Field::RelativePath => path
+            .strip_prefix(visit.root)
+            .unwrap_or(path)
+            .as_os_str()
+            .as_encoded_bytes()
+            .to_vec(),
+        Field::StartingPoint => visit.root.as_os_str().as_encoded_bytes().to_vec(),
+        Field::Name => entry.file_name().as_encoded_bytes().to_vec(),
+        Field::Dir => match path.parent() {
+            Some(parent) if parent != Path::new("") => {
+                parent.as_os_str().as_encoded_bytes().to_vec()
+            }
+            _ => b".".to_vec(),
+        },
+        Field::Type => vec![type_char(entry.file_type())],
+        Field::Depth => entry.depth().to_string().into_bytes(),
+        _ => match visit.metadata() {
+            Some(metadata) => metadata_value(field, metadata).into_bytes(),
+            None => vec![],
+        },
+    }
+}
+
+fn metadata_value(field: Field, metadata: &Metadata) -> String {
+    match field {
+        Field::Size => metadata.len().to_string(),
+        #[cfg(unix)]
+        Field::Blocks => kilobytes(metadata.blocks()).to_string(),
+        #[cfg(unix)]
+        Field::OctalMode => format!("{:o}", metadata.mode() & 0o7777),
+        #[cfg(unix)]
+        Field::SymbolicMode => format_mode(metadata.mode()),
+        #[cfg(unix)]
+        Field::User => users::get_user_by_uid(metadata.uid())
+            .map(|user| user.name().to_string_lossy().into_owned())
+            .unwrap_or_else(|| metadata.uid().to_string()),
+        #[cfg(unix)]
+        Field::Uid => metadata.uid().to_string(),
+        #[cfg(unix)]
+        Field::Group => users::get_group_by_gid(metadata.gid())
+            .map(|group| group.name().to_string_lossy().into_owned())
+            .unwrap_or_else(|| metadata.gid().to_string()),
+        #[cfg(unix)]
+        Field::Gid => metadata.gid().to_string(),
+        #[cfg(unix)]
+        Field::Inode => metadata.ino().to_string(),
+        #[cfg(unix)]
+        Field::Links => metadata.nlink().to_string(),
+        Field::Time(kind, conversion) => epoch_time(metadata, kind)
+            .map(|(secs, nanos)| format_time(secs, nanos, conversion))
+            .unwrap_or_default(),
+        _ => unreachable!("{:?} doesn't need metadata", field),
+    }
+}
+
+/// The space taken by `blocks` 512-byte blocks, rounded up to whole
+/// kilobytes like GNU find.
+#[cfg(unix)]
+fn kilobytes(blocks: u64) -> u64 {
+    blocks.div_ceil(2)
+}
+
+/// Seconds and nanoseconds since the epoch of the given time.
+#[cfg(unix)]
+fn epoch_time(metadata: &Metadata, kind: TimeKind) -> Option<(i64, i64)> {
+    Some(match kind {
+        TimeKind::Accessed => (metadata.atime(), metadata.atime_nsec()),
+        TimeKind::Changed => (metadata.ctime(), metadata.ctime_nsec()),
+        TimeKind::Modified => (metadata.mtime(), metadata.mtime_nsec()),
+    })
+}
+
+/// Seconds and nanoseconds since the epoch of the given time, when the
+/// platform records it.
+#[cfg(not(unix))]
+fn epoch_time(metadata: &Metadata, kind: TimeKind) -> Option<(i64, i64)> {
+    let time = match kind {
+        TimeKind::Accessed => metadata.accessed(),
+        TimeKind::Modified => metadata.modified(),
+    };
+    Some(match time.ok()?.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos() as i64),
+        Err(e) => (-(e.duration().as_secs() as i64), 0),
+    })
+}
+
+fn format_time(secs: i64, nanos: i64, conversion: char) -> String {
+    if conversion == '@' {
+        return format!("{}.{:09}", secs, nanos);
+    }
+    let time = match secs >= 0 {
+        true => UNIX_EPOCH + std::time::Duration::new(secs as u64, nanos as u32),
+        false => UNIX_EPOCH - std::time::Duration::from_secs(secs.unsigned_abs()),
+    };
+    let time: DateTime<Local> = SystemTime::into(time);
+    time.format(&format!("%{}", conversion)).to_string()
+}
+
+#[cfg(unix)]
+fn type_char(file_type: FileType) -> u8 {
+    match file_type {
+        t if t.is_dir() => b'd',
+        t if t.is_symlink() => b'l',
+        t if t.is_socket() => b's',
+        t if t.is_fifo() => b'p',
+        t if t.is_block_device() => b'b',
+        t if t.is_char_device() => b'c',
+        _ => b'f',
+    }
+}
+
+#[cfg(not(unix))]
+fn type_char(file_type: FileType) -> u8 {
+    match file_type {
+        t if t.is_dir() => b'd',
+        t if t.is_symlink() => b'l',
+        _ => b'f',
+    }
+}
+
+fn truncate(value: Vec<u8>, chars: usize) -> Vec<u8> {
+    match String::from_utf8(value) {
+        Ok(text) => text.chars().take(chars).collect::<String>().into_bytes(),
+        Err(e) => e.into_bytes().into_iter().take(chars).collect(),
+    }
+}
+
+fn char_count(value: &[u8]) -> usize {
+    std::str::from_utf8(value).map_or(value.len(), |text| text.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_time, Format, Piece};
+
+    fn literal(format: &str) -> Vec<u8> {
+        match &Format::parse(format).unwrap().0[..] {
+            [Piece::Literal(bytes)] => bytes.clone(),
+            pieces => panic!("not a literal: {:?}", pieces),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(literal(r"a\tb\n"), b"a\tb\n");
+        assert_eq!(literal(r"\0\101\\\q"), b"\x00A\\\\q");
+        assert_eq!(literal("100%%"), b"100%");
+
+        let format = Format::parse("%-10p|%5.2f %TY-%Tm-%Td\n").unwrap();
+        assert_eq!(format.0.len(), 10);
+        assert!(matches!(
+            format.0[0],
+            Piece::Directive {
+                left_align: true,
+                width: 10,
+                precision: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            format.0[2],
+            Piece::Directive {
+                left_align: false,
+                width: 5,
+                precision: Some(2),
+                ..
+            }
+        ));
+
+        for bad in ["%", "%z", "%T", "%TQ", "%5"] {
+            assert_eq!(
+                Format::parse(bad).unwrap_err().to_string(),
+                format!("Invalid --printf format \"{}\"", bad)
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(1_500_000_000, 5, '@'), "1500000000.000000005");
+        assert_eq!(format_time(0, 0, 'Y').len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kilobytes() {
+        use super::kilobytes;
+
+        assert_eq!(kilobytes(0), 0);
+        assert_eq!(kilobytes(1), 1);
+        assert_eq!(kilobytes(2), 1);
+        assert_eq!(kilobytes(7), 4);
+    }
+}
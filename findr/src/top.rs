@@ -0,0 +1,155 @@
+use crate::{predicate::Visit, MyResult};
+use chrono::{DateTime, Local};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::Write,
+    mem,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// What `--top` ranks entries by.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortKey {
+    Size,
+    Modified,
+    Accessed,
+}
+
+impl SortKey {
+    pub fn parse(val: &str) -> MyResult<SortKey> {
+        match val {
+            "size" => Ok(SortKey::Size),
+            "mtime" => Ok(SortKey::Modified),
+            "atime" => Ok(SortKey::Accessed),
+            _ => Err(From::from(format!("Invalid --by \"{}\"", val))),
+        }
+    }
+}
+
+/// A ranked entry: its key, made negative to rank the smallest first,
+/// and then its path, so that ties go to the first path.
+type Ranked = (i128, Reverse<PathBuf>);
+
+/// Keeps the `count` largest, newest or most recently accessed entries
+/// the expression is true for, or the smallest or oldest with
+/// `--reverse`. Only that many are held at any time.
+#[derive(Debug)]
+pub struct Top {
+    count: usize,
+    key: SortKey,
+    reverse: bool,
+    /// The lowest ranked entry kept is on top
+    heap: Mutex<BinaryHeap<Reverse<Ranked>>>,
+}
+
+impl Top {
+    pub fn new(count: usize, key: SortKey, reverse: bool) -> Top {
+        Top {
+            count,
+            key,
+            reverse,
+            heap: Mutex::new(BinaryHeap::with_capacity(count + 1)),
+        }
+    }
+
+    pub fn add(&self, visit: &mut Visit) {
+        if self.count == 0 {
+            return;
+        }
+        let path = visit.entry.path().to_path_buf();
+        let Some(metadata) = visit.metadata() else {
+            return;
+        };
+        let time = match self.key {
+            SortKey::Size => return self.push(metadata.len().into(), path),
+            SortKey::Modified => metadata.modified(),
+            SortKey::Accessed => metadata.accessed(),
+        };
+        if let Ok(time) = time {
+            self.push(nanos(time), path);
+        }
+    }
+
+    fn push(&self, key: i128, path: PathBuf) {
+        let rank = match self.reverse {
+            true => -key,
+            false => key,
+        };
+        let mut heap = self.heap.lock().unwrap();
+        heap.push(Reverse((rank, Reverse(path))));
+        if heap.len() > self.count {
+            heap.pop();
+        }
+    }
+
+    /// Prints the entries kept, best first, each as its key, a tab and its
+    /// path. Times are local.
+    pub fn print(&self, out: &mut impl Write) -> MyResult<()> {
+        let heap = mem::take(&mut *self.heap.lock().unwrap());
+        for Reverse((rank, Reverse(path))) in heap.into_sorted_vec() {
+            let key = match self.reverse {
+                true => -rank,
+                false => rank,
+            };
+            writeln!(out, "{}\t{}", self.format_key(key), path.display())?;
+        }
+        Ok(())
+    }
+
+    fn format_key(&self, key: i128) -> String {
+        if self.key == SortKey::Size {
+            return key.to_string();
+        }
+        let time = match u64::try_from(key) {
+            Ok(nanos) => UNIX_EPOCH + Duration::from_nanos(nanos),
+            Err(_) => UNIX_EPOCH - Duration::from_nanos(key.unsigned_abs() as u64),
+        };
+        let time: DateTime<Local> = time.into();
+        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// Nanoseconds since 1970, negative for earlier times.
+fn nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SortKey, Top};
+
+    fn top(count: usize, reverse: bool, entries: &[(i128, &str)]) -> String {
+        let top = Top::new(count, SortKey::Size, reverse);
+        for (key, path) in entries {
+            top.push(*key, path.into());
+        }
+        let mut out = vec![];
+        top.print(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_top() {
+        let entries = [(5, "e"), (1, "a"), (9, "i"), (5, "d"), (3, "c")];
+        assert_eq!(top(3, false, &entries), "9\ti\n5\td\n5\te\n");
+        assert_eq!(top(2, true, &entries), "1\ta\n3\tc\n");
+        assert_eq!(top(10, false, &entries).lines().count(), 5);
+        assert_eq!(top(0, false, &entries), "");
+        assert_eq!(top(1, false, &[]), "");
+    }
+
+    #[test]
+    fn test_parse_sort_key() {
+        assert_eq!(SortKey::parse("mtime").unwrap(), SortKey::Modified);
+        assert_eq!(
+            SortKey::parse("name").unwrap_err().to_string(),
+            "Invalid --by \"name\""
+        );
+    }
+}
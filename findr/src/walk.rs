@@ -0,0 +1,320 @@
+use crate::{predicate::Visit, Config};
+use rayon::prelude::*;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::{
+    ffi::OsStr,
+    fs::{self, FileType, Metadata},
+    io::{self, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A file or directory found by the walk, from `WalkDir` or from the
+/// parallel walk below.
+#[derive(Debug)]
+pub struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    depth: usize,
+    /// A symbolic link that was followed, so its metadata is the target's
+    follow: bool,
+}
+
+impl Entry {
+    fn from_path(path: PathBuf, depth: usize, follow: bool) -> io::Result<Entry> {
+        let metadata = match follow {
+            true => fs::metadata(&path)?,
+            false => fs::symlink_metadata(&path)?,
+        };
+        Ok(Entry {
+            path,
+            file_type: metadata.file_type(),
+            depth,
+            follow,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        match self.follow {
+            true => fs::metadata(&self.path),
+            false => fs::symlink_metadata(&self.path),
+        }
+    }
+}
+
+impl From<walkdir::DirEntry> for Entry {
+    fn from(entry: walkdir::DirEntry) -> Entry {
+        let file_type = entry.file_type();
+        Entry {
+            follow: entry.path_is_symlink() && !file_type.is_symlink(),
+            depth: entry.depth(),
+            path: entry.into_path(),
+            file_type,
+        }
+    }
+}
+
+/// Walks the tree under `root` on the current thread pool, handing each
+/// directory's entries out to whichever threads are free. Entries are
+/// written to `out` as they are found, or with `--sort`, gathered and
+/// written at the end in the order of a sorted serial walk.
+pub fn walk_parallel<W: Write + Send>(
+    config: &Config,
+    root: &str,
+    out: &Mutex<W>,
+) -> io::Result<()> {
+    let root = Path::new(root);
+    let entry = match Entry::from_path(root.to_path_buf(), 0, config.follow_links) {
+        Ok(entry) => entry,
+        Err(e) => {
+            report_io_error(root, &e);
+            return Ok(());
+        }
+    };
+    let walk = Walk {
+        config,
+        root,
+        device: match config.same_file_system {
+            true => fs::metadata(root)
+                .ok()
+                .and_then(|metadata| device_of(&metadata)),
+            false => None,
+        },
+        out,
+    };
+    let sorted = walk.visit(entry, None)?;
+    out.lock().unwrap().write_all(&sorted)
+}
+
+/// Prints an error about `path` the way `WalkDir` does.
+pub fn report_io_error(path: &Path, e: &io::Error) {
+    eprintln!("IO error for operation on {}: {}", path.display(), e);
+}
+
+struct Walk<'a, W> {
+    config: &'a Config,
+    root: &'a Path,
+    /// The device of the root, with `-xdev`
+    device: Option<u64>,
+    out: &'a Mutex<W>,
+}
+
+/// A directory being walked, kept with `-L` to find loops.
+struct Ancestor<'a> {
+    path: &'a Path,
+    id: DirId,
+    parent: Option<&'a Ancestor<'a>>,
+}
+
+/// What tells directories apart when looking for loops: the device and
+/// inode on Unix, and the canonical path elsewhere.
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+#[cfg(unix)]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    fs::metadata(path).map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    fs::canonicalize(path)
+}
+
+/// The device holding the file, which is only known on Unix. Elsewhere
+/// `-xdev` is left to the serial walk.
+#[cfg(unix)]
+fn device_of(metadata: &Metadata) -> Option<u64> {
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_of(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+impl<W: Write + Send> Walk<'_, W> {
+    /// Evaluates the expression for `entry` and walks its contents,
+    /// returning what was printed if it's to be sorted.
+    fn visit(&self, entry: Entry, parent: Option<&Ancestor>) -> io::Result<Vec<u8>> {
+        let config = self.config;
+        let mut out = Output {
+            buf: vec![],
+            shared: (!config.sort).then_some(self.out),
+        };
+        let mut descend = self.is_dir(&entry)
+            && config.max_depth.is_none_or(|depth| entry.depth < depth)
+            && self.on_device(&entry);
+
+        // Like WalkDir, a directory that loops back is reported in its
+        // place
+        let mut id = None;
+        if descend && config.follow_links {
+            match dir_id(&entry.path) {
+                Ok(dir) => id = Some(dir),
+                Err(e) => {
+                    report_io_error(&entry.path, &e);
+                    return Ok(vec![]);
+                }
+            }
+            let mut ancestor = parent;
+            while let Some(dir) = ancestor {
+                if Some(&dir.id) == id.as_ref() {
+                    eprintln!(
+                        "File system loop detected; '{}' is part of the same file system loop as '{}'.",
+                        entry.path.display(),
+                        dir.path.display()
+                    );
+                    return Ok(vec![]);
+                }
+                ancestor = dir.parent;
+            }
+        }
+
+        let shown = config.min_depth.is_none_or(|depth| entry.depth >= depth);
+        if shown && !config.contents_first {
+            let mut visit = Visit::new(&entry, self.root);
+            config.eval(&mut visit, &mut out)?;
+            descend &= !visit.prune;
+            out.commit()?;
+        }
+        if descend {
+            let dir = id.map(|id| Ancestor {
+                path: &entry.path,
+                id,
+                parent,
+            });
+            let contents = self
+                .read_dir(&entry)
+                .into_par_iter()
+                .map(|child| self.visit(child, dir.as_ref()))
+                .collect::<io::Result<Vec<_>>>()?;
+            out.buf.extend(contents.into_iter().flatten());
+        }
+        if shown && config.contents_first {
+            config.eval(&mut Visit::new(&entry, self.root), &mut out)?;
+        }
+        out.commit()?;
+        Ok(out.buf)
+    }
+
+    /// Whether `entry` is a directory to walk into, which a link given as
+    /// a starting point is when it points to one.
+    fn is_dir(&self, entry: &Entry) -> bool {
+        entry.file_type.is_dir()
+            || (entry.depth == 0
+                && entry.file_type.is_symlink()
+                && fs::metadata(&entry.path).is_ok_and(|metadata| metadata.is_dir()))
+    }
+
+    fn on_device(&self, entry: &Entry) -> bool {
+        match self.device {
+            Some(device) if entry.depth > 0 => match entry.metadata() {
+                Ok(metadata) => device_of(&metadata) == Some(device),
+                Err(e) => {
+                    report_io_error(&entry.path, &e);
+                    false
+                }
+            },
+            _ => true,
+        }
+    }
+
+    fn skips(&self, entry: &Entry) -> bool {
+        let is_dir = entry.file_type.is_dir();
+        self.config.filter.skips(&entry.path, is_dir, entry.depth)
+    }
+
+    fn read_dir(&self, dir: &Entry) -> Vec<Entry> {
+        let read_dir = match fs::read_dir(&dir.path) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                report_io_error(&dir.path, &e);
+                return vec![];
+            }
+        };
+        let depth = dir.depth + 1;
+        let mut entries = vec![];
+        for result in read_dir {
+            let entry = result.and_then(|dir_entry| {
+                let file_type = dir_entry.file_type()?;
+                match self.config.follow_links && file_type.is_symlink() {
+                    true => Entry::from_path(dir_entry.path(), depth, true),
+                    false => Ok(Entry {
+                        path: dir_entry.path(),
+                        file_type,
+                        depth,
+                        follow: false,
+                    }),
+                }
+            });
+            match entry {
+                Ok(entry) if self.skips(&entry) => {}
+                Ok(entry) => entries.push(entry),
+                Err(e) => report_io_error(&dir.path, &e),
+            }
+        }
+        if self.config.sort {
+            entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        }
+        entries
+    }
+}
+
+/// What is printed for an entry, passed on to the shared output a whole
+/// entry at a time so that lines from different threads don't mix. When
+/// sorting it's kept instead, to be put in order.
+struct Output<'a, W> {
+    buf: Vec<u8>,
+    shared: Option<&'a Mutex<W>>,
+}
+
+impl<W: Write> Output<'_, W> {
+    fn commit(&mut self) -> io::Result<()> {
+        match self.shared {
+            Some(shared) if !self.buf.is_empty() => {
+                let result = shared.lock().unwrap().write_all(&self.buf);
+                self.buf.clear();
+                result
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for Output<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.shared {
+            Some(shared) => {
+                let mut shared = shared.lock().unwrap();
+                shared.write_all(&mem::take(&mut self.buf))?;
+                shared.flush()
+            }
+            None => Ok(()),
+        }
+    }
+}
@@ -1,7 +1,15 @@
+#![allow(clippy::needless_borrows_for_generic_args, mismatched_lifetime_syntaxes)]
+
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
-use std::{borrow::Cow, fs, path::Path};
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -39,17 +47,22 @@ fn skips_bad_dir() -> TestResult {
 #[test]
 fn dies_bad_name() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["--name", "*.csv"])
+        .args(&["--name-regex", "--name", "*.csv"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid --name \"*.csv\""));
+    Command::cargo_bin(PRG)?
+        .args(&["--name", "[.csv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --name \"[.csv\""));
     Ok(())
 }
 
 // --------------------------------------------------
 #[test]
 fn dies_bad_type() -> TestResult {
-    let expected = "error: 'x' isn't a valid value for '--type <TYPE>...'";
+    let expected = "Invalid --type \"x\"";
     Command::cargo_bin(PRG)?
         .args(&["--type", "x"])
         .assert()
@@ -58,6 +71,39 @@ fn dies_bad_type() -> TestResult {
     Ok(())
 }
 
+// --------------------------------------------------
+#[cfg(not(unix))]
+#[test]
+fn dies_unix_only() -> TestResult {
+    for (args, expected) in [
+        (&["-user", "root"][..], "--user is not supported on this platform"),
+        (&["-ctime", "1"], "--ctime is not supported on this platform"),
+        (&["-printf", "%u"], "%u in --printf is not supported on this platform"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(expected));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+#[test]
+fn dies_non_utf8_arg() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    Command::cargo_bin(PRG)?
+        .arg(OsStr::from_bytes(b"tests/inputs/\xff"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid argument \"tests/inputs/\u{FFFD}\"",
+        ));
+    Ok(())
+}
+
 // --------------------------------------------------
 #[cfg(windows)]
 fn format_file_name(expected_file: &str) -> Cow<str> {
@@ -219,7 +265,7 @@ fn type_l() -> TestResult {
 #[test]
 fn type_f_l() -> TestResult {
     run(
-        &["tests/inputs", "-t", "l", "f"],
+        &["tests/inputs", "-t", "l,f"],
         "tests/expected/type_f_l.txt",
     )
 }
@@ -228,7 +274,7 @@ fn type_f_l() -> TestResult {
 #[test]
 fn name_csv() -> TestResult {
     run(
-        &["tests/inputs", "-n", ".*[.]csv"],
+        &["tests/inputs", "-n", "*.csv"],
         "tests/expected/name_csv.txt",
     )
 }
@@ -237,7 +283,7 @@ fn name_csv() -> TestResult {
 #[test]
 fn name_csv_mp3() -> TestResult {
     run(
-        &["tests/inputs", "-n", ".*[.]csv", "-n", ".*[.]mp3"],
+        &["tests/inputs", "-n", "*.csv", "-o", "-n", "*.mp3"],
         "tests/expected/name_csv_mp3.txt",
     )
 }
@@ -246,7 +292,7 @@ fn name_csv_mp3() -> TestResult {
 #[test]
 fn name_txt_path_a_d() -> TestResult {
     run(
-        &["tests/inputs/a", "tests/inputs/d", "--name", ".*.txt"],
+        &["tests/inputs/a", "tests/inputs/d", "--name", "*.txt"],
         "tests/expected/name_txt_path_a_d.txt",
     )
 }
@@ -254,14 +300,36 @@ fn name_txt_path_a_d() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn name_a() -> TestResult {
-    run(&["tests/inputs", "-n", "a"], "tests/expected/name_a.txt")
+    run(&["tests/inputs", "-n", "*a*"], "tests/expected/name_a.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn name_regex() -> TestResult {
+    run(
+        &["tests/inputs", "--name-regex", "-n", ".*[.]csv"],
+        "tests/expected/name_csv.txt",
+    )?;
+    run(
+        &["tests/inputs", "-t", "f", "--name-regex", "-n", "a"],
+        "tests/expected/type_f_name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn iname() -> TestResult {
+    run(
+        &["tests/inputs", "-iname", "*.CSV"],
+        "tests/expected/name_csv.txt",
+    )
 }
 
 // --------------------------------------------------
 #[test]
 fn type_f_name_a() -> TestResult {
     run(
-        &["tests/inputs", "-t", "f", "-n", "a"],
+        &["tests/inputs", "-t", "f", "-n", "*a*"],
         "tests/expected/type_f_name_a.txt",
     )
 }
@@ -270,7 +338,7 @@ fn type_f_name_a() -> TestResult {
 #[test]
 fn type_d_name_a() -> TestResult {
     run(
-        &["tests/inputs", "--type", "d", "--name", "a"],
+        &["tests/inputs", "--type", "d", "--name", "*a*"],
         "tests/expected/type_d_name_a.txt",
     )
 }
@@ -281,6 +349,996 @@ fn path_g() -> TestResult {
     run(&["tests/inputs/g.csv"], "tests/expected/path_g.txt")
 }
 
+// --------------------------------------------------
+fn tree() -> Result<TempDir, Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 60 * 60);
+    for (name, size, age) in [
+        ("empty.txt", 0, 0),
+        ("small.txt", 100, 1),
+        ("block.txt", 512, 3),
+        ("big.bin", 3000, 10),
+    ] {
+        let file = File::create(dir.path().join(name))?;
+        file.set_len(size)?;
+        file.set_modified(now - day * age - Duration::from_secs(60))?;
+    }
+    fs::create_dir(dir.path().join("empty_dir"))?;
+    fs::create_dir(dir.path().join("full_dir"))?;
+    File::create(dir.path().join("full_dir/x"))?.set_len(1)?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+fn run_tree(dir: &TempDir, args: &[&str], expected: &[&str]) -> TestResult {
+    let root = dir.path().to_str().unwrap();
+    let cmd = Command::cargo_bin(PRG)?
+        .arg(root)
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut lines: Vec<&str> = stdout
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.strip_prefix(root).unwrap().trim_start_matches('/'))
+        .collect();
+    lines.sort();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn size() -> TestResult {
+    let dir = tree()?;
+    run_tree(
+        &dir,
+        &["--type", "f", "--size", "1"],
+        &["block.txt", "full_dir/x", "small.txt"],
+    )?;
+    run_tree(&dir, &["--type", "f", "-size", "+1"], &["big.bin"])?;
+    run_tree(&dir, &["--type", "f", "--size", "-1"], &["empty.txt"])?;
+    run_tree(
+        &dir,
+        &["--type", "f", "--size", "+100c"],
+        &["big.bin", "block.txt"],
+    )?;
+    run_tree(&dir, &["--type", "f", "--size", "3k"], &["big.bin"])?;
+    run_tree(
+        &dir,
+        &["--type", "f", "--size", "+1k", "--size", "-2M"],
+        &["big.bin"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--size", "1x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --size \"1x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn mtime() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["--type", "f", "--mtime", "1"], &["small.txt"])?;
+    run_tree(
+        &dir,
+        &["--type", "f", "-mtime", "+2"],
+        &["big.bin", "block.txt"],
+    )?;
+    run_tree(
+        &dir,
+        &["--type", "f", "--mtime", "-1"],
+        &["empty.txt", "full_dir/x"],
+    )?;
+    run_tree(
+        &dir,
+        &["--type", "f", "--mmin", "-2"],
+        &["empty.txt", "full_dir/x"],
+    )?;
+    run_tree(&dir, &["--type", "f", "-mmin", "+5000"], &["big.bin"])
+}
+
+// --------------------------------------------------
+#[test]
+fn newer() -> TestResult {
+    let dir = tree()?;
+    let reference = dir.path().join("block.txt");
+    run_tree(
+        &dir,
+        &["--type", "f", "-newer", reference.to_str().unwrap()],
+        &["empty.txt", "full_dir/x", "small.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_newer() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", &bad);
+    Command::cargo_bin(PRG)?
+        .args(["--newer", &bad])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["-empty"], &["empty.txt", "empty_dir"])?;
+    run_tree(&dir, &["--empty", "--type", "d"], &["empty_dir"])
+}
+
+// --------------------------------------------------
+#[test]
+fn path() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["-path", "*/full_dir/*"], &["full_dir/x"])?;
+    run_tree(
+        &dir,
+        &["-ipath", "*/FULL_*", "-o", "-path", "*.bin"],
+        &["big.bin", "full_dir", "full_dir/x"],
+    )?;
+    run_tree(&dir, &["-path", "full_dir/*"], &[])
+}
+
+// --------------------------------------------------
+#[test]
+fn regex() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["-regex", ".*/full_dir/.*"], &["full_dir/x"])?;
+    run_tree(&dir, &["-iregex", r".*/BIG\.BIN"], &["big.bin"])?;
+    run_tree(&dir, &["-regex", "full_dir/x"], &[])?;
+    run_tree(&dir, &["-regex", "x"], &[])
+}
+
+// --------------------------------------------------
+#[test]
+fn depth() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["--maxdepth", "0"], &[""])?;
+    run_tree(&dir, &["-mindepth", "2"], &["full_dir/x"])?;
+    run_tree(
+        &dir,
+        &["-maxdepth", "1", "-mindepth", "1", "--type", "d"],
+        &["empty_dir", "full_dir"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_depth() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--maxdepth", "one"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --maxdepth \"one\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn prune() -> TestResult {
+    let dir = tree()?;
+    run_tree(
+        &dir,
+        &["-name", "full*", "-prune", "-o", "-type", "d", "-print"],
+        &["", "empty_dir"],
+    )?;
+    run_tree(
+        &dir,
+        &["-name", "full*", "-prune", "-o", "-name", "x"],
+        &["full_dir"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expression() -> TestResult {
+    let dir = tree()?;
+    run_tree(
+        &dir,
+        &[
+            "(", "-name", "*.txt", "-o", "-type", "d", ")", "-a", "!", "-empty",
+        ],
+        &["", "block.txt", "full_dir", "small.txt"],
+    )?;
+    run_tree(&dir, &["-type", "f", "-not", "-size", "-2"], &["big.bin"])?;
+    run_tree(
+        &dir,
+        &[
+            "-name", "empty*", "-print", "-o", "-type", "f", "-size", "+1", "-print",
+        ],
+        &["big.bin", "empty.txt", "empty_dir"],
+    )?;
+    run_tree(&dir, &["-false", "-print"], &[])
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_expression() -> TestResult {
+    for (args, expected) in [
+        (&["(", "-empty"][..], "Missing \")\" in expression"),
+        (
+            &["-empty", "extra"],
+            "Paths must precede the expression: \"extra\"",
+        ),
+        (&["-bogus"], "Unknown predicate \"-bogus\""),
+        (&["-name"], "Missing argument to --name"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(expected));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links() -> TestResult {
+    let dir = tree()?;
+    std::os::unix::fs::symlink("full_dir", dir.path().join("link"))?;
+    run_tree(&dir, &["--name", "x"], &["full_dir/x"])?;
+    run_tree(&dir, &["-L", "--name", "x"], &["full_dir/x", "link/x"])?;
+    run_tree(&dir, &["-L", "--type", "l"], &[])?;
+    run_tree(&dir, &["--xdev", "--type", "l"], &["link"])
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links_loop() -> TestResult {
+    let dir = tree()?;
+    std::os::unix::fs::symlink("..", dir.path().join("full_dir/up"))?;
+    let root = dir.path().to_str().unwrap();
+    let expected = format!(
+        "File system loop detected; '{}/full_dir/up' is part of the same \
+         file system loop as '{}'.",
+        root, root
+    );
+    Command::cargo_bin(PRG)?
+        .args([root, "-L", "--name", "up"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(expected));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec() -> TestResult {
+    let dir = tree()?;
+    run_tree(
+        &dir,
+        &["-type", "f", "-exec", "test", "-s", "{}", ";", "-print"],
+        &["big.bin", "block.txt", "full_dir/x", "small.txt"],
+    )?;
+    run_tree(
+        &dir,
+        &["-name", "x", "-exec", "echo", "{}", ";"],
+        &["full_dir/x"],
+    )?;
+
+    let root = dir.path().to_str().unwrap();
+    let cmd = Command::cargo_bin(PRG)?
+        .args([root, "-name", "*.txt", "-exec", "echo", "files:", "{}", "+"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut words: Vec<&str> = stdout.split_whitespace().collect();
+    words.sort();
+    let expected = [
+        format!("{}/block.txt", root),
+        format!("{}/empty.txt", root),
+        format!("{}/small.txt", root),
+        "files:".to_string(),
+    ];
+    assert_eq!(words, expected);
+    assert_eq!(stdout.lines().count(), 1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_non_utf8_name() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join(OsStr::from_bytes(b"a\xff"));
+    fs::write(&file, "")?;
+    Command::cargo_bin(PRG)?
+        .arg(dir.path())
+        .args(["-type", "f", "-exec", "rm", "{}", ";"])
+        .assert()
+        .success();
+    assert!(!file.exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn execdir() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([
+            root,
+            "-name",
+            "x",
+            "-execdir",
+            "sh",
+            "-c",
+            "echo $PWD {}",
+            ";",
+        ])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}/full_dir ./x\n",
+            fs::canonicalize(root)?.display()
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn ok() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    for (answer, expected) in [("y\n", "yes\n"), ("n\n", "")] {
+        Command::cargo_bin(PRG)?
+            .args([root, "-name", "x", "-ok", "echo", "yes", ";"])
+            .write_stdin(answer)
+            .assert()
+            .success()
+            .stdout(expected)
+            .stderr(predicate::str::contains("< echo yes > ? "));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_fails() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "x", "-exec", "false", "{}", "+"])
+        .assert()
+        .failure()
+        .stderr("A command run by -exec ... + failed\n");
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "x", "-execdir", "false", "{}", "+"])
+        .assert()
+        .failure()
+        .stderr("A command run by -execdir ... + failed\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete() -> TestResult {
+    let dir = tree()?;
+    run_tree(&dir, &["-name", "*.txt", "-delete"], &[])?;
+    run_tree(&dir, &["-type", "f"], &["big.bin", "full_dir/x"])?;
+
+    let full_dir = dir.path().join("full_dir");
+    Command::cargo_bin(PRG)?
+        .arg(&full_dir)
+        .arg("-delete")
+        .assert()
+        .success();
+    assert!(!full_dir.exists());
+
+    Command::cargo_bin(PRG)?
+        .arg(dir.path())
+        .args(["-name", "empty_dir", "-o", "-type", "d", "-delete"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Cannot delete"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm() -> TestResult {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tree()?;
+    for (name, mode) in [
+        ("big.bin", 0o755),
+        ("block.txt", 0o640),
+        ("empty.txt", 0o600),
+        ("small.txt", 0o644),
+        ("full_dir/x", 0o4755),
+    ] {
+        let perms = fs::Permissions::from_mode(mode);
+        fs::set_permissions(dir.path().join(name), perms)?;
+    }
+    let files = ["-type", "f", "-perm"];
+    let run_perm =
+        |mode, expected: &[&str]| run_tree(&dir, &[&files[..], &[mode]].concat(), expected);
+    run_perm("644", &["small.txt"])?;
+    run_perm("u=rw,go=r", &["small.txt"])?;
+    run_perm("-644", &["big.bin", "full_dir/x", "small.txt"])?;
+    run_perm("-u+s", &["full_dir/x"])?;
+    run_perm("/o+w", &[])?;
+    run_perm(
+        "/g+r,o+x",
+        &["big.bin", "block.txt", "full_dir/x", "small.txt"],
+    )?;
+    run_perm(
+        "/0",
+        &[
+            "big.bin",
+            "block.txt",
+            "empty.txt",
+            "full_dir/x",
+            "small.txt",
+        ],
+    )?;
+    run_tree(
+        &dir,
+        &["-type", "f", "-executable"],
+        &["big.bin", "full_dir/x"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_perm() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-perm", "u+z"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --perm \"u+z\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn owner() -> TestResult {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tree()?;
+    let meta = fs::metadata(dir.path())?;
+    let (uid, gid) = (meta.uid().to_string(), meta.gid().to_string());
+    let all = [
+        "",
+        "big.bin",
+        "block.txt",
+        "empty.txt",
+        "empty_dir",
+        "full_dir",
+        "full_dir/x",
+        "small.txt",
+    ];
+    run_tree(&dir, &["-user", &uid, "-group", &gid], &all)?;
+    run_tree(&dir, &["!", "-user", &uid], &[])?;
+    run_tree(&dir, &["-nouser", "-o", "-nogroup"], &[])?;
+
+    let unknown = (u32::MAX - 1).to_string();
+    Command::cargo_bin(PRG)?
+        .args([dir.path().to_str().unwrap(), "-user", &unknown])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_user() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-user", "no such user"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --user \"no such user\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn special_types() -> TestResult {
+    let dir = tree()?;
+    let fifo = dir.path().join("fifo");
+    std::process::Command::new("mkfifo").arg(&fifo).status()?;
+    let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("socket"))?;
+    run_tree(&dir, &["-type", "p"], &["fifo"])?;
+    run_tree(&dir, &["-type", "s"], &["socket"])?;
+    run_tree(&dir, &["-type", "s,p"], &["fifo", "socket"])?;
+    run_tree(&dir, &["-type", "b,c"], &[])?;
+    Command::cargo_bin(PRG)?
+        .args(["/dev", "-maxdepth", "1", "-name", "null", "-type", "c"])
+        .assert()
+        .success()
+        .stdout("/dev/null\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn print0() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "x", "-print0", "-name", "x", "-print0"])
+        .assert()
+        .success()
+        .stdout(format!("{0}/full_dir/x\0{0}/full_dir/x\0", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn printf() -> TestResult {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    let x = dir.path().join("full_dir/x");
+    fs::set_permissions(&x, fs::Permissions::from_mode(0o640))?;
+    File::options()
+        .write(true)
+        .open(&x)?
+        .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(86400 * 365))?;
+
+    let format = r"%p|%P|%f|%h|%d|%y|%s|%m|%M|%TY-%Tm|%T@|%%\t%-4.2f|%4s\n";
+    let expected = format!(
+        "{0}/full_dir/x|full_dir/x|x|{0}/full_dir|2|f|1|640|-rw-r-----|\
+         1971-01|31536000.000000000|%\tx   |   1\n",
+        root
+    );
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "x", "-printf", format])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Command::cargo_bin(PRG)?
+        .args([root, "-maxdepth", "0", "-printf", "%y %d %P|%H\n"])
+        .assert()
+        .success()
+        .stdout(format!("d 0 |{}\n", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_printf() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-printf", "%Z"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --printf format \"%Z\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fprint() -> TestResult {
+    let dir = tree()?;
+    let out_dir = tempfile::tempdir()?;
+    let root = dir.path().to_str().unwrap();
+    let list = out_dir.path().join("list");
+    let sizes = out_dir.path().join("sizes");
+    Command::cargo_bin(PRG)?
+        .arg(root)
+        .args(["-name", "*.txt", "-fprint"])
+        .arg(&list)
+        .args(["-o", "-name", "*.bin", "-fprintf"])
+        .arg(&sizes)
+        .args(["%f %s\n", "-o", "-name", "x"])
+        .assert()
+        .success()
+        .stdout("");
+
+    let contents = fs::read_to_string(&list)?;
+    let mut lines: Vec<&str> = contents.lines().collect();
+    lines.sort();
+    let expected: Vec<String> = ["block.txt", "empty.txt", "small.txt"]
+        .iter()
+        .map(|name| format!("{}/{}", root, name))
+        .collect();
+    assert_eq!(lines, expected);
+    assert_eq!(fs::read_to_string(&sizes)?, "big.bin 3000\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn print_streams_with_exec() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "x", "-print", "-exec", "echo", "ran", ";"])
+        .args(["-printf", "%f\n"])
+        .assert()
+        .success()
+        .stdout(format!("{}/full_dir/x\nran\nx\n", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads() -> TestResult {
+    let dir = tree()?;
+    let all = [
+        "",
+        "big.bin",
+        "block.txt",
+        "empty.txt",
+        "empty_dir",
+        "full_dir",
+        "full_dir/x",
+        "small.txt",
+    ];
+    run_tree(&dir, &["--threads", "4"], &all)?;
+    run_tree(&dir, &["--threads", "0", "-depth"], &all)?;
+    run_tree(
+        &dir,
+        &[
+            "--threads",
+            "4",
+            "-name",
+            "full_dir",
+            "-prune",
+            "-o",
+            "-print",
+        ],
+        &[
+            "",
+            "big.bin",
+            "block.txt",
+            "empty.txt",
+            "empty_dir",
+            "small.txt",
+        ],
+    )?;
+    run_tree(
+        &dir,
+        &[
+            "--threads",
+            "4",
+            "-mindepth",
+            "1",
+            "-maxdepth",
+            "1",
+            "-empty",
+        ],
+        &["empty.txt", "empty_dir"],
+    )?;
+    run_tree(
+        &dir,
+        &["--threads", "4", "-type", "f", "-size", "+1"],
+        &["big.bin"],
+    )?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_sorted() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    for args in [
+        &["-printf", "%d %P\n"][..],
+        &["-depth", "-printf", "%P\n"],
+        &[
+            "-name", "*.txt", "-print", "-o", "-type", "d", "-printf", "[%f]\n",
+        ],
+    ] {
+        let serial = Command::cargo_bin(PRG)?
+            .args([root, "--sort"])
+            .args(args)
+            .assert()
+            .success();
+        let parallel = Command::cargo_bin(PRG)?
+            .args([root, "--threads", "4", "--sort"])
+            .args(args)
+            .assert()
+            .success();
+        assert_eq!(serial.get_output().stdout, parallel.get_output().stdout);
+    }
+
+    Command::cargo_bin(PRG)?
+        .args(["--threads", "3", "--sort", root, "-printf", "%P\n"])
+        .assert()
+        .success()
+        .stdout(
+            "\nbig.bin\nblock.txt\nempty.txt\nempty_dir\nfull_dir\nfull_dir/x\n\
+             small.txt\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn threads_follow_links() -> TestResult {
+    let dir = tree()?;
+    std::os::unix::fs::symlink("../full_dir", dir.path().join("empty_dir/link"))?;
+    std::os::unix::fs::symlink("..", dir.path().join("full_dir/up"))?;
+    let root = dir.path().to_str().unwrap();
+    let expected = format!(
+        "File system loop detected; '{}/full_dir/up' is part of the same \
+         file system loop as '{}'.",
+        root, root
+    );
+    Command::cargo_bin(PRG)?
+        .args([root, "-L", "--threads", "4", "--sort", "-name", "x"])
+        .assert()
+        .success()
+        .stdout(format!("{0}/empty_dir/link/x\n{0}/full_dir/x\n", root))
+        .stderr(predicate::str::contains(expected));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_threads() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--threads", "many"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --threads \"many\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_ignoring(dir: &TempDir, home: &Path, args: &[&str], expected: &[&str]) -> TestResult {
+    let root = dir.path().join("repo");
+    let root = root.to_str().unwrap();
+    let cmd = Command::cargo_bin(PRG)?
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .arg(root)
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut lines: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.strip_prefix(root).unwrap().trim_start_matches('/'))
+        .collect();
+    lines.sort();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let home = dir.path().join("home");
+    fs::create_dir_all(home.join("config/git"))?;
+    fs::write(home.join("config/git/ignore"), "*.swp\n")?;
+
+    let repo = dir.path().join("repo");
+    for sub in [".git/info", "src/gen", "target/debug"] {
+        fs::create_dir_all(repo.join(sub))?;
+    }
+    fs::write(repo.join(".git/info/exclude"), "notes.txt\n")?;
+    fs::write(repo.join(".gitignore"), "/target/\n*.log\n!keep.log\n")?;
+    fs::write(repo.join("src/.ignore"), "gen/\n")?;
+    for file in [
+        ".env",
+        "a.log",
+        "keep.log",
+        "notes.txt",
+        "src/main.rs",
+        "src/main.rs.swp",
+        "src/gen/out.rs",
+        "target/debug/findr",
+    ] {
+        File::create(repo.join(file))?;
+    }
+
+    let visible = ["", "keep.log", "src", "src/main.rs"];
+    run_ignoring(&dir, &home, &["--respect-ignore"], &visible)?;
+    run_ignoring(&dir, &home, &["--respect-ignore", "-depth"], &visible)?;
+    run_ignoring(
+        &dir,
+        &home,
+        &["--respect-ignore", "--threads", "2"],
+        &visible,
+    )?;
+    run_ignoring(
+        &dir,
+        &home,
+        &["--respect-ignore", "--hidden", "-maxdepth", "1"],
+        &["", ".env", ".git", ".gitignore", "keep.log", "src"],
+    )?;
+
+    // Without --respect-ignore, nothing is skipped
+    run_ignoring(&dir, &home, &["-name", "*.log"], &["a.log", "keep.log"])?;
+    run_ignoring(&dir, &home, &["--hidden", "-name", ".env"], &[".env"])?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn duplicates() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().to_str().unwrap();
+    let big: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    let mut changed = big.clone();
+    changed[4999] = 0;
+    fs::create_dir(dir.path().join("sub"))?;
+    for (name, contents) in [
+        ("a.bin", &big[..]),
+        ("sub/b.bin", &big),
+        ("c.bin", &changed),
+        ("d.txt", b"same"),
+        ("sub/e.txt", b"same"),
+        ("f.txt", b"other"),
+        ("empty1", b""),
+        ("empty2", b""),
+    ] {
+        fs::write(dir.path().join(name), contents)?;
+    }
+    fs::hard_link(dir.path().join("a.bin"), dir.path().join("z.bin"))?;
+
+    let expected = format!(
+        "{0}/a.bin\n{0}/sub/b.bin\n\n{0}/d.txt\n{0}/sub/e.txt\n",
+        root
+    );
+    for args in [
+        &["--duplicates"][..],
+        &["--duplicates", "--threads", "2"],
+        &["--duplicates", "--threads", "4", "-depth"],
+    ] {
+        Command::cargo_bin(PRG)?
+            .arg(root)
+            .args(args)
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+
+    Command::cargo_bin(PRG)?
+        .args([root, "--duplicates", "--min-size", "1k"])
+        .assert()
+        .success()
+        .stdout(format!("{0}/a.bin\n{0}/sub/b.bin\n", root));
+
+    Command::cargo_bin(PRG)?
+        .args([root, "--duplicates", "--min-size", "0", "-name", "empty*"])
+        .assert()
+        .success()
+        .stdout(format!("{0}/empty1\n{0}/empty2\n", root));
+
+    Command::cargo_bin(PRG)?
+        .args([
+            root,
+            "-name",
+            "*.txt",
+            "-duplicates",
+            "-o",
+            "-name",
+            "c.bin",
+        ])
+        .assert()
+        .success()
+        .stdout(format!("{0}/d.txt\n{0}/sub/e.txt\n", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_min_size() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--duplicates", "--min-size", "1x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --min-size \"1x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn top() -> TestResult {
+    let dir = tree()?;
+    let root = dir.path().to_str().unwrap();
+    let largest = format!("3000\t{0}/big.bin\n512\t{0}/block.txt\n", root);
+    for args in [
+        &["--top", "2"][..],
+        &["--top", "2", "--threads", "2"],
+        &["--top", "2", "--threads", "4", "-depth"],
+    ] {
+        Command::cargo_bin(PRG)?
+            .args([root, "-type", "f"])
+            .args(args)
+            .assert()
+            .success()
+            .stdout(largest.clone());
+    }
+
+    Command::cargo_bin(PRG)?
+        .args([
+            root,
+            "--top",
+            "2",
+            "--by",
+            "size",
+            "--reverse",
+            "-type",
+            "f",
+        ])
+        .assert()
+        .success()
+        .stdout(format!("0\t{0}/empty.txt\n1\t{0}/full_dir/x\n", root));
+
+    let oldest = format!(r"^\d{{4}}-\d\d-\d\d \d\d:\d\d:\d\d\t{}/big\.bin\n$", root);
+    Command::cargo_bin(PRG)?
+        .args([
+            root,
+            "--top",
+            "1",
+            "--by",
+            "mtime",
+            "--reverse",
+            "-type",
+            "f",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(oldest)?);
+
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "*.txt", "-top", "5", "-by", "mtime"])
+        .assert()
+        .success()
+        .stdout(predicate::function(|out: &str| {
+            let names: Vec<&str> = out
+                .lines()
+                .map(|line| line.rsplit('/').next().unwrap())
+                .collect();
+            names == ["empty.txt", "small.txt", "block.txt"]
+        }));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_top() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--top", "many"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --top \"many\""));
+    Command::cargo_bin(PRG)?
+        .args(["--top", "1", "--by", "ctime"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --by \"ctime\""));
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 #[cfg(not(windows))]
@@ -0,0 +1,160 @@
+use std::env;
+
+const DEFAULT_GREP_COLORS: &str = "ms=01;31:mc=01;31:sl=:cx=:fn=35:ln=32:bn=32:se=36";
+
+/// SGR sequences used to highlight output, in the format of GNU grep's
+/// `GREP_COLORS` variable. `bn` is accepted but has no effect, as byte
+/// offsets are never printed, and `rv` isn't supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    selected_match: String,
+    context_match: String,
+    selected_line: String,
+    context_line: String,
+    filename: String,
+    line_number: String,
+    separator: String,
+    erase_line: bool,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors::parse(DEFAULT_GREP_COLORS)
+    }
+}
+
+impl Colors {
+    /// Defaults overridden by whatever capabilities `GREP_COLORS` sets.
+    pub fn from_env() -> Colors {
+        let mut colors = Colors::default();
+        if let Ok(spec) = env::var("GREP_COLORS") {
+            colors.apply(&spec);
+        }
+        colors
+    }
+
+    fn parse(spec: &str) -> Colors {
+        let mut colors = Colors {
+            selected_match: String::new(),
+            context_match: String::new(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            filename: String::new(),
+            line_number: String::new(),
+            separator: String::new(),
+            erase_line: true,
+        };
+        colors.apply(spec);
+        colors
+    }
+
+    fn apply(&mut self, spec: &str) {
+        for cap in spec.split(':') {
+            let (name, value) = cap.split_once('=').unwrap_or((cap, ""));
+            let value = value.to_string();
+            match name {
+                "mt" => {
+                    self.selected_match = value.clone();
+                    self.context_match = value;
+                }
+                "ms" => self.selected_match = value,
+                "mc" => self.context_match = value,
+                "sl" => self.selected_line = value,
+                "cx" => self.context_line = value,
+                "fn" => self.filename = value,
+                "ln" => self.line_number = value,
+                "se" => self.separator = value,
+                "ne" => self.erase_line = false,
+                _ => {}
+            }
+        }
+    }
+
+    fn paint(&self, sgr: &str, text: &[u8]) -> Vec<u8> {
+        if sgr.is_empty() || text.is_empty() {
+            return text.to_vec();
+        }
+        [self.start(sgr).as_bytes(), text, self.end().as_bytes()].concat()
+    }
+
+    fn start(&self, sgr: &str) -> String {
+        match self.erase_line {
+            true => format!("\x1b[{}m\x1b[K", sgr),
+            false => format!("\x1b[{}m", sgr),
+        }
+    }
+
+    fn end(&self) -> &'static str {
+        match self.erase_line {
+            true => "\x1b[m\x1b[K",
+            false => "\x1b[m",
+        }
+    }
+
+    pub fn filename(&self, filename: &str) -> Vec<u8> {
+        self.paint(&self.filename, filename.as_bytes())
+    }
+
+    pub fn line_number(&self, line_number: usize) -> Vec<u8> {
+        self.paint(&self.line_number, line_number.to_string().as_bytes())
+    }
+
+    pub fn separator(&self, separator: &str) -> Vec<u8> {
+        self.paint(&self.separator, separator.as_bytes())
+    }
+
+    pub fn selected_match(&self, text: &[u8]) -> Vec<u8> {
+        self.paint(&self.selected_match, text)
+    }
+
+    pub fn context_match(&self, text: &[u8]) -> Vec<u8> {
+        self.paint(&self.context_match, text)
+    }
+
+    pub fn selected_line(&self, text: &[u8]) -> Vec<u8> {
+        self.paint(&self.selected_line, text)
+    }
+
+    pub fn context_line(&self, text: &[u8]) -> Vec<u8> {
+        self.paint(&self.context_line, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Colors;
+
+    #[test]
+    fn test_parse() {
+        let colors = Colors::default();
+        assert_eq!(colors.selected_match, "01;31");
+        assert_eq!(colors.context_match, "01;31");
+        assert_eq!(colors.filename, "35");
+        assert_eq!(colors.separator, "36");
+        assert!(colors.erase_line);
+
+        let mut colors = Colors::default();
+        colors.apply("mt=01;32:fn=:ne:xx=1");
+        assert_eq!(colors.selected_match, "01;32");
+        assert_eq!(colors.context_match, "01;32");
+        assert_eq!(colors.filename, "");
+        assert_eq!(colors.line_number, "32");
+        assert!(!colors.erase_line);
+
+        colors.apply("ms=33:mc=34");
+        assert_eq!(colors.selected_match, "33");
+        assert_eq!(colors.context_match, "34");
+    }
+
+    #[test]
+    fn test_paint() {
+        let colors = Colors::parse("ms=31:ne");
+        assert_eq!(colors.selected_match(b"foo"), b"\x1b[31mfoo\x1b[m");
+        assert_eq!(colors.selected_match(b""), b"");
+        assert_eq!(colors.selected_line(b"foo"), b"foo");
+        assert_eq!(colors.filename("x"), b"x");
+
+        let colors = Colors::parse("fn=35");
+        assert_eq!(colors.filename("x"), b"\x1b[35m\x1b[Kx\x1b[m\x1b[K");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{pattern::Pattern, BinaryFiles, Config, MyResult, Report, Stats};
+use similar::TextDiff;
+use std::{
+    fs,
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tempfile::NamedTempFile;
+
+/// Replaces every match in `filename` with `--replace`, rewriting the file
+/// or, with `--dry-run`, reporting the changes as a unified diff.
+pub fn edit_file(config: &Config, filename: &str) -> Report {
+    match try_edit_file(config, filename) {
+        Ok(report) => report,
+        Err(e) => Report::error(format!("{}: {}", filename, e)),
+    }
+}
+
+fn try_edit_file(config: &Config, filename: &str) -> MyResult<Report> {
+    if filename == "-" {
+        return Err(From::from("standard input can't be edited in place"));
+    }
+    let start = Instant::now();
+    let original = fs::read(filename)?;
+    let terminator = config.searcher.line_terminator;
+    let binary = config.binary_files != BinaryFiles::Text && terminator != 0;
+    if binary && original.contains(&0) {
+        return Ok(Report::default());
+    }
+
+    let (edited, lines, replaced) = replace_all(config, &original)?;
+    let mut report = Report {
+        matched: replaced > 0,
+        stats: Stats {
+            elapsed: start.elapsed(),
+            searches: 1,
+            searches_with_match: usize::from(replaced > 0),
+            bytes_searched: original.len(),
+            matched_lines: lines,
+            matches: replaced,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    if edited == original {
+        return Ok(report);
+    }
+    if config.dry_run {
+        TextDiff::from_lines(&original[..], &edited[..])
+            .unified_diff()
+            .header(filename, filename)
+            .to_writer(&mut report.output)?;
+    } else {
+        write_atomically(filename, &edited, config.backup.as_deref())?;
+    }
+    Ok(report)
+}
+
+/// Returns `buf` with every match replaced, along with the number of lines
+/// changed and the number of matches.
+fn replace_all(config: &Config, buf: &[u8]) -> MyResult<(Vec<u8>, usize, usize)> {
+    let pattern = &config.searcher.pattern;
+    let template = config.replace.as_deref().unwrap_or_default();
+    let terminator = config.searcher.line_terminator;
+    if config.searcher.multiline {
+        let (result, spans) = substitute(pattern, template, buf)?;
+        let lines = lines_touched(buf, &spans, terminator);
+        return Ok((result, lines, spans.len()));
+    }
+
+    let mut result = Vec::with_capacity(buf.len());
+    let mut lines = 0;
+    let mut replaced = 0;
+    for line in buf.split_inclusive(|&b| b == terminator) {
+        let content = line.strip_suffix(&[terminator]).unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        let (new_content, spans) = substitute(pattern, template, content)?;
+        result.extend(new_content);
+        result.extend(&line[content.len()..]);
+        lines += usize::from(!spans.is_empty());
+        replaced += spans.len();
+    }
+    Ok((result, lines, replaced))
+}
+
+/// Returns `haystack` with every non-empty match replaced, along with the
+/// spans that were replaced.
+fn substitute(
+    pattern: &Pattern,
+    template: &str,
+    haystack: &[u8],
+) -> MyResult<(Vec<u8>, Vec<Range<usize>>)> {
+    let mut result = vec![];
+    let mut last = 0;
+    let mut spans = vec![];
+    for caps in pattern.captures_iter(haystack)? {
+        let m = caps.get(0).unwrap();
+        if m.is_empty() {
+            continue;
+        }
+        result.extend(&haystack[last..m.start]);
+        caps.expand(template, &mut result);
+        last = m.end;
+        spans.push(m);
+    }
+    result.extend(&haystack[last..]);
+    Ok((result, spans))
+}
+
+/// Number of distinct lines of `buf` that the non-empty `spans`, given in
+/// order, fall on. A match spanning several lines touches each of them.
+fn lines_touched(buf: &[u8], spans: &[Range<usize>], terminator: u8) -> usize {
+    let count = |bytes: &[u8]| bytes.iter().filter(|&&b| b == terminator).count();
+    let mut touched = 0;
+    // The line at `pos`, and the last line counted
+    let (mut line, mut pos) = (0, 0);
+    let mut last_counted = None;
+    for span in spans {
+        let first = line + count(&buf[pos..span.start]);
+        line = first + count(&buf[span.start..span.end - 1]);
+        pos = span.end - 1;
+        touched += line - first + 1 - usize::from(last_counted == Some(first));
+        last_counted = Some(line);
+    }
+    touched
+}
+
+/// Writes `contents` to a temporary file beside `filename` and renames it
+/// over the original, so readers never see a partly written file.
+fn write_atomically(filename: &str, contents: &[u8], backup: Option<&str>) -> MyResult<()> {
+    // Edit the target of a symlink rather than replacing the link
+    let path = fs::canonicalize(filename)?;
+    let dir = path
+        .parent()
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let permissions = fs::metadata(&path)?.permissions();
+
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(contents)?;
+    temp.as_file().set_permissions(permissions)?;
+    if let Some(suffix) = backup {
+        fs::copy(&path, format!("{}{}", filename, suffix))?;
+    }
+    temp.persist(&path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lines_touched;
+    use std::ops::Range;
+
+    #[test]
+    fn test_lines_touched() {
+        let buf = b"a b\nc\nd\ne\n";
+        assert_eq!(lines_touched(buf, &[], b'\n'), 0);
+        // Two matches on one line
+        assert_eq!(lines_touched(buf, &[0..1, 2..3], b'\n'), 1);
+        // A match from the first line into the second and one after it on
+        // the second
+        assert_eq!(lines_touched(buf, &[2..5, 5..6], b'\n'), 2);
+        // A match ending with its line's terminator stays on that line
+        assert_eq!(lines_touched(buf, &[4..6, 8..10], b'\n'), 2);
+        let all = Range { start: 0, end: 10 };
+        assert_eq!(lines_touched(buf, &[all], b'\n'), 4);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{LineKind, LineMatch, Stats};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+// Messages follow the format of ripgrep's --json output, one per line.
+
+pub fn begin(path: &str) -> Vec<u8> {
+    message("begin", json!({ "path": data(path_bytes(path)) }))
+}
+
+pub fn line(path: &str, line: &LineMatch) -> Vec<u8> {
+    let kind = match line.kind {
+        LineKind::Match => "match",
+        LineKind::Context => "context",
+    };
+    let submatches: Vec<Value> = line
+        .submatches
+        .iter()
+        .map(|range| {
+            json!({
+                "match": data(&line.line[range.clone()]),
+                "start": range.start,
+                "end": range.end,
+            })
+        })
+        .collect();
+    message(
+        kind,
+        json!({
+            "path": data(path_bytes(path)),
+            "lines": data(&line.line),
+            "line_number": line.line_number,
+            "absolute_offset": line.absolute_offset,
+            "submatches": submatches,
+        }),
+    )
+}
+
+pub fn end(path: &str, binary_offset: Option<usize>, stats: &Stats) -> Vec<u8> {
+    message(
+        "end",
+        json!({
+            "path": data(path_bytes(path)),
+            "binary_offset": binary_offset,
+            "stats": stats_value(stats),
+        }),
+    )
+}
+
+pub fn summary(stats: &Stats, elapsed_total: Duration) -> Vec<u8> {
+    message(
+        "summary",
+        json!({
+            "elapsed_total": duration(elapsed_total),
+            "stats": stats_value(stats),
+        }),
+    )
+}
+
+fn message(kind: &str, data: Value) -> Vec<u8> {
+    let mut out = json!({ "type": kind, "data": data })
+        .to_string()
+        .into_bytes();
+    out.push(b'\n');
+    out
+}
+
+fn path_bytes(path: &str) -> &[u8] {
+    match path {
+        "-" => b"<stdin>",
+        _ => path.as_bytes(),
+    }
+}
+
+/// Text that isn't valid UTF-8 is sent base64-encoded.
+fn data(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": STANDARD.encode(bytes) }),
+    }
+}
+
+fn stats_value(stats: &Stats) -> Value {
+    json!({
+        "elapsed": duration(stats.elapsed),
+        "searches": stats.searches,
+        "searches_with_match": stats.searches_with_match,
+        "bytes_searched": stats.bytes_searched,
+        "bytes_printed": stats.bytes_printed,
+        "matched_lines": stats.matched_lines,
+        "matches": stats.matches,
+    })
+}
+
+fn duration(duration: Duration) -> Value {
+    json!({
+        "secs": duration.as_secs(),
+        "nanos": duration.subsec_nanos(),
+        "human": format!("{:.6}s", duration.as_secs_f64()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data, line};
+    use crate::{LineKind, LineMatch};
+    use serde_json::{json, Value};
+    use std::ops::Range;
+
+    #[test]
+    fn test_data() {
+        assert_eq!(data(b"foo"), json!({ "text": "foo" }));
+        assert_eq!(data(b"\xff"), json!({ "bytes": "/w==" }));
+    }
+
+    #[test]
+    fn test_line() {
+        let m = LineMatch {
+            kind: LineKind::Match,
+            line_number: 2,
+            absolute_offset: 6,
+            line: b"a foo\n"[..].into(),
+            submatches: vec![Range { start: 2, end: 5 }],
+            line_count: 1,
+        };
+        let value: Value = serde_json::from_slice(&line("-", &m)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "type": "match",
+                "data": {
+                    "path": { "text": "<stdin>" },
+                    "lines": { "text": "a foo\n" },
+                    "line_number": 2,
+                    "absolute_offset": 6,
+                    "submatches": [{ "match": { "text": "foo" }, "start": 2, "end": 5 }],
+                }
+            })
+        );
+    }
+}
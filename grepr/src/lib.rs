@@ -1,22 +1,85 @@
+mod color;
+mod edit;
+mod json;
+mod pattern;
+mod search;
+
+pub use search::{LineKind, LineMatch, Searcher, SearcherBuilder, Sink};
+
 use std::{
+    collections::BTreeMap,
     error::Error,
     fs::{self, File},
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    iter,
+    num::NonZeroUsize,
+    ops::Range,
+    path::Path,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::{App, Arg};
-use regex::{Regex, RegexBuilder};
-use walkdir::WalkDir;
+use clap::{App, Arg, ErrorKind};
+use color::Colors;
+use flate2::bufread::MultiGzDecoder;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use pattern::Captures;
+use xz2::bufread::XzDecoder;
+
+/// The result of the library's fallible functions. The error can be sent
+/// between threads.
+pub type MyResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug, Eq, PartialEq)]
+enum ListFiles {
+    WithMatches,
+    WithoutMatch,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum BinaryFiles {
+    Binary,
+    Text,
+    WithoutMatch,
+}
 
-type MyResult<T> = Result<T, Box<dyn Error>>;
+#[derive(Debug, Default)]
+struct WalkOptions {
+    recursive: bool,
+    no_ignore: bool,
+    hidden: bool,
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    exclude_dir: Option<GlobSet>,
+    sort: bool,
+}
 
 #[derive(Debug)]
 pub struct Config {
-    pattern: Regex,
+    searcher: Searcher,
+    search_zip: bool,
     files: Vec<String>,
-    recursive: bool,
+    walk: WalkOptions,
+    threads: usize,
     count: bool,
-    invert_match: bool,
+    count_matches: bool,
+    stats: bool,
+    list_files: Option<ListFiles>,
+    quiet: bool,
+    binary_files: BinaryFiles,
+    json: bool,
+    line_number: bool,
+    null: bool,
+    only_matching: bool,
+    only_group: Option<String>,
+    replace: Option<String>,
+    in_place: bool,
+    dry_run: bool,
+    backup: Option<String>,
+    colors: Option<Colors>,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -29,13 +92,69 @@ pub fn get_args() -> MyResult<Config> {
     let pattern_arg = Arg::with_name("pattern")
         .value_name("PATTERN")
         .help("Search Pattern")
-        .required(true);
+        .required_unless_one(&["regexp", "pattern_file"]);
+
+    let regexp_arg = Arg::with_name("regexp")
+        .value_name("PATTERN")
+        .short("e")
+        .long("regexp")
+        .help("Search pattern (may be repeated)")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    let pattern_file_arg = Arg::with_name("pattern_file")
+        .value_name("FILE")
+        .short("f")
+        .long("file")
+        .help("Read patterns from FILE, one per line")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    let fixed_strings_arg = Arg::with_name("fixed_strings")
+        .short("F")
+        .long("fixed-strings")
+        .help("Treat patterns as literal strings");
+
+    let word_regexp_arg = Arg::with_name("word_regexp")
+        .short("w")
+        .long("word-regexp")
+        .help("Match only whole words");
+
+    let line_regexp_arg = Arg::with_name("line_regexp")
+        .short("x")
+        .long("line-regexp")
+        .help("Match only whole lines");
+
+    let multiline_arg = Arg::with_name("multiline")
+        .short("U")
+        .long("multiline")
+        .help("Allow matches to span lines");
+
+    let perl_regexp_arg = Arg::with_name("perl_regexp")
+        .short("P")
+        .long("perl-regexp")
+        .help("Use a backtracking engine supporting lookaround and backreferences");
+
+    let search_zip_arg = Arg::with_name("search_zip")
+        .short("z")
+        .long("search-zip")
+        .help("Search inside gzip, xz and zstd compressed files");
 
     let count_arg = Arg::with_name("count")
         .short("c")
         .long("count")
         .help("Count occurrences");
 
+    let count_matches_arg = Arg::with_name("count_matches")
+        .long("count-matches")
+        .help("Count every match rather than matching lines");
+
+    let stats_arg = Arg::with_name("stats")
+        .long("stats")
+        .help("Print search statistics to stderr");
+
     let insensitive_arg = Arg::with_name("insensitive")
         .short("i")
         .long("insensitive")
@@ -51,182 +170,1033 @@ pub fn get_args() -> MyResult<Config> {
         .long("invert-match")
         .help("Invert match");
 
+    let no_ignore_arg = Arg::with_name("no_ignore")
+        .long("no-ignore")
+        .help("Don't respect .gitignore and .ignore files");
+
+    let hidden_arg = Arg::with_name("hidden")
+        .long("hidden")
+        .help("Search hidden files and directories");
+
+    let include_arg = Arg::with_name("include")
+        .value_name("GLOB")
+        .long("include")
+        .help("Search only files whose name matches GLOB")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    let exclude_arg = Arg::with_name("exclude")
+        .value_name("GLOB")
+        .long("exclude")
+        .help("Skip files whose name matches GLOB")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    let exclude_dir_arg = Arg::with_name("exclude_dir")
+        .value_name("GLOB")
+        .long("exclude-dir")
+        .help("Skip directories whose name matches GLOB")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1);
+
+    let sort_arg = Arg::with_name("sort")
+        .value_name("SORTBY")
+        .long("sort")
+        .help("Sort results")
+        .possible_values(&["path", "none"])
+        .default_value("none");
+
+    let threads_arg = Arg::with_name("threads")
+        .value_name("NUM")
+        .short("j")
+        .long("threads")
+        .help("Number of threads to search with")
+        .takes_value(true);
+
+    let files_with_matches_arg = Arg::with_name("files_with_matches")
+        .short("l")
+        .long("files-with-matches")
+        .help("Print only names of files with matches")
+        .conflicts_with("files_without_match");
+
+    let files_without_match_arg = Arg::with_name("files_without_match")
+        .short("L")
+        .long("files-without-match")
+        .help("Print only names of files without matches");
+
+    let quiet_arg = Arg::with_name("quiet")
+        .short("q")
+        .long("quiet")
+        .help("Suppress output, exit with zero status on any match");
+
+    let max_count_arg = Arg::with_name("max_count")
+        .value_name("NUM")
+        .short("m")
+        .long("max-count")
+        .help("Stop reading a file after NUM selected lines")
+        .takes_value(true);
+
+    let binary_files_arg = Arg::with_name("binary_files")
+        .value_name("TYPE")
+        .long("binary-files")
+        .help("How to treat files containing NUL bytes")
+        .possible_values(&["binary", "text", "without-match"])
+        .default_value("binary");
+
+    let text_arg = Arg::with_name("text")
+        .short("a")
+        .long("text")
+        .help("Search binary files as if they were text");
+
+    let skip_binary_arg = Arg::with_name("skip_binary")
+        .short("I")
+        .help("Skip binary files, like --binary-files=without-match");
+
+    let after_context_arg = Arg::with_name("after_context")
+        .value_name("NUM")
+        .short("A")
+        .long("after-context")
+        .help("Print NUM lines of trailing context")
+        .takes_value(true);
+
+    let before_context_arg = Arg::with_name("before_context")
+        .value_name("NUM")
+        .short("B")
+        .long("before-context")
+        .help("Print NUM lines of leading context")
+        .takes_value(true);
+
+    let context_arg = Arg::with_name("context")
+        .value_name("NUM")
+        .short("C")
+        .long("context")
+        .help("Print NUM lines of context")
+        .takes_value(true);
+
+    let json_arg = Arg::with_name("json")
+        .long("json")
+        .help("Print results as JSON Lines")
+        .conflicts_with_all(&[
+            "count",
+            "count_matches",
+            "files_with_matches",
+            "files_without_match",
+            "quiet",
+            "only_matching",
+            "only_group",
+            "replace",
+        ]);
+
+    let line_number_arg = Arg::with_name("line_number")
+        .short("n")
+        .long("line-number")
+        .help("Print line numbers");
+
+    let null_arg = Arg::with_name("null")
+        .short("Z")
+        .long("null")
+        .help("Follow filenames with a NUL byte instead of ':' or a newline");
+
+    let null_data_arg = Arg::with_name("null_data")
+        .long("null-data")
+        .help("Treat lines as terminated by NUL bytes (not -z, which is --search-zip)");
+
+    let only_matching_arg = Arg::with_name("only_matching")
+        .short("o")
+        .long("only-matching")
+        .help("Print only the matched parts of lines");
+
+    let only_group_arg = Arg::with_name("only_group")
+        .value_name("GROUP")
+        .long("only-group")
+        .help("Print only the given capture group of each match")
+        .takes_value(true)
+        .conflicts_with("replace");
+
+    let replace_arg = Arg::with_name("replace")
+        .value_name("TEMPLATE")
+        .long("replace")
+        .help("Replace matches with TEMPLATE ($1, ${name})")
+        .takes_value(true);
+
+    let in_place_arg = Arg::with_name("in_place")
+        .long("in-place")
+        .help("Rewrite files with matches replaced")
+        .requires("replace")
+        .conflicts_with_all(&[
+            "count",
+            "count_matches",
+            "files_with_matches",
+            "files_without_match",
+            "quiet",
+            "json",
+            "only_matching",
+            "only_group",
+            "invert",
+            "max_count",
+            "search_zip",
+        ]);
+
+    let dry_run_arg = Arg::with_name("dry_run")
+        .long("dry-run")
+        .help("Show the changes --in-place would make as a unified diff")
+        .requires("in_place");
+
+    let backup_arg = Arg::with_name("backup")
+        .value_name("SUFFIX")
+        .long("backup")
+        .help("Keep a copy of each edited file with SUFFIX appended")
+        .takes_value(true)
+        .requires("in_place")
+        .conflicts_with("dry_run");
+
+    let color_arg = Arg::with_name("color")
+        .value_name("WHEN")
+        .long("color")
+        .help("Highlight matches")
+        .possible_values(&["auto", "always", "never"])
+        .min_values(0)
+        .require_equals(true)
+        .default_value("auto");
+
     let matches = App::new("grepr")
         .version("0.1.0")
         .author("Celal Taş <celal.tas123@gmail.com>")
         .about("Rust grep")
+        .arg(regexp_arg)
+        .arg(pattern_file_arg)
+        .arg(fixed_strings_arg)
+        .arg(word_regexp_arg)
+        .arg(line_regexp_arg)
+        .arg(multiline_arg)
+        .arg(perl_regexp_arg)
+        .arg(search_zip_arg)
         .arg(count_arg)
+        .arg(count_matches_arg)
+        .arg(stats_arg)
         .arg(insensitive_arg)
         .arg(invert_arg)
         .arg(recursive_arg)
+        .arg(no_ignore_arg)
+        .arg(hidden_arg)
+        .arg(include_arg)
+        .arg(exclude_arg)
+        .arg(exclude_dir_arg)
+        .arg(sort_arg)
+        .arg(threads_arg)
+        .arg(files_with_matches_arg)
+        .arg(files_without_match_arg)
+        .arg(quiet_arg)
+        .arg(max_count_arg)
+        .arg(binary_files_arg)
+        .arg(text_arg)
+        .arg(skip_binary_arg)
+        .arg(after_context_arg)
+        .arg(before_context_arg)
+        .arg(context_arg)
+        .arg(json_arg)
+        .arg(line_number_arg)
+        .arg(null_arg)
+        .arg(null_data_arg)
+        .arg(only_matching_arg)
+        .arg(only_group_arg)
+        .arg(replace_arg)
+        .arg(in_place_arg)
+        .arg(dry_run_arg)
+        .arg(backup_arg)
+        .arg(color_arg)
         .arg(pattern_arg)
         .arg(file_args)
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|e| match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(2)
+            }
+        });
+
+    let mut files = matches.values_of_lossy("files").unwrap();
+    let patterns = if matches.is_present("regexp") || matches.is_present("pattern_file") {
+        // With -e or -f, the first positional argument is a file
+        if let Some(file) = matches.value_of("pattern") {
+            if matches.occurrences_of("files") == 0 {
+                files.clear();
+            }
+            files.insert(0, file.to_string());
+        }
+        let mut patterns = matches.values_of_lossy("regexp").unwrap_or_default();
+        for filename in matches.values_of_lossy("pattern_file").unwrap_or_default() {
+            let contents =
+                fs::read_to_string(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+            patterns.extend(contents.lines().map(String::from));
+        }
+        patterns
+    } else {
+        matches.values_of_lossy("pattern").unwrap()
+    };
+
+    let parse_num = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|num| {
+                num.parse::<usize>()
+                    .map_err(|_| format!("Invalid --{} \"{}\"", name.replace('_', "-"), num))
+            })
+            .transpose()
+    };
+    let max_count = parse_num("max_count")?;
+    let context = parse_num("context")?.unwrap_or(0);
+    let mut before_context = parse_num("before_context")?.unwrap_or(context);
+    let mut after_context = parse_num("after_context")?.unwrap_or(context);
+
+    let threads = match matches.value_of("threads") {
+        Some(num) => num
+            .parse::<NonZeroUsize>()
+            .map_err(|_| format!("Invalid --threads \"{}\"", num))?,
+        None => thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+    };
+
+    let walk = WalkOptions {
+        recursive: matches.is_present("recursive"),
+        no_ignore: matches.is_present("no_ignore"),
+        hidden: matches.is_present("hidden"),
+        include: build_globs(matches.values_of_lossy("include"))?,
+        exclude: build_globs(matches.values_of_lossy("exclude"))?,
+        exclude_dir: build_globs(matches.values_of_lossy("exclude_dir"))?,
+        sort: matches.value_of("sort") == Some("path"),
+    };
+
+    let list_files = if matches.is_present("files_with_matches") {
+        Some(ListFiles::WithMatches)
+    } else if matches.is_present("files_without_match") {
+        Some(ListFiles::WithoutMatch)
+    } else {
+        None
+    };
+
+    let binary_files = if matches.is_present("text") {
+        BinaryFiles::Text
+    } else if matches.is_present("skip_binary") {
+        BinaryFiles::WithoutMatch
+    } else {
+        match matches.value_of("binary_files") {
+            Some("text") => BinaryFiles::Text,
+            Some("without-match") => BinaryFiles::WithoutMatch,
+            _ => BinaryFiles::Binary,
+        }
+    };
+
+    let quiet = matches.is_present("quiet");
+    let count_matches = matches.is_present("count_matches");
+    let count = matches.is_present("count") || count_matches;
+    let only_group = matches.value_of("only_group").map(String::from);
+    let only_matching = matches.is_present("only_matching") || only_group.is_some();
+    // Context is only shown around whole selected lines
+    let in_place = matches.is_present("in_place");
+    if count || quiet || list_files.is_some() || only_matching || in_place {
+        before_context = 0;
+        after_context = 0;
+    }
 
-    let pattern = matches.value_of("pattern").unwrap();
-    let pattern = RegexBuilder::new(pattern)
+    let searcher = Searcher::builder()
+        .patterns(&patterns)
+        .fixed_strings(matches.is_present("fixed_strings"))
+        .word_regexp(matches.is_present("word_regexp"))
+        .line_regexp(matches.is_present("line_regexp"))
         .case_insensitive(matches.is_present("insensitive"))
-        .build()
-        .map_err(|_e| format!("Invalid pattern \"{}\"", pattern))?;
+        .multiline(matches.is_present("multiline"))
+        .perl_regexp(matches.is_present("perl_regexp"))
+        .invert_match(matches.is_present("invert"))
+        .before_context(before_context)
+        .after_context(after_context)
+        .max_count(max_count)
+        .line_terminator(match matches.is_present("null_data") {
+            true => b'\0',
+            false => b'\n',
+        })
+        .build()?;
+
+    if let Some(group) = &only_group {
+        let valid = match group.parse::<usize>() {
+            Ok(index) => index < searcher.pattern.captures_len(),
+            Err(_) => searcher.pattern.has_group(group),
+        };
+        if !valid {
+            return Err(From::from(format!("Invalid --only-group \"{}\"", group)));
+        }
+    }
+
+    let colors = match matches.value_of("color") {
+        Some("always") => true,
+        Some("auto") => io::stdout().is_terminal(),
+        _ => false,
+    }
+    .then(Colors::from_env);
 
     Ok(Config {
-        pattern: pattern,
-        files: matches.values_of_lossy("files").unwrap(),
-        recursive: matches.is_present("recursive"),
-        count: matches.is_present("count"),
-        invert_match: matches.is_present("invert"),
+        searcher,
+        search_zip: matches.is_present("search_zip"),
+        files,
+        walk,
+        threads: threads.get(),
+        count,
+        count_matches,
+        stats: matches.is_present("stats"),
+        list_files,
+        quiet,
+        binary_files,
+        json: matches.is_present("json"),
+        line_number: matches.is_present("line_number"),
+        null: matches.is_present("null"),
+        only_matching,
+        only_group,
+        replace: matches.value_of("replace").map(String::from),
+        in_place,
+        dry_run: matches.is_present("dry_run"),
+        backup: matches.value_of("backup").map(String::from),
+        colors,
     })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let entries = find_files(&config.files, config.recursive);
-    let num_files = entries.len();
-    let print = |fname: &str, val: &str| {
-        if num_files > 1 {
-            print!("{}:{}", fname, val);
-        } else {
-            print!("{}", val);
+fn build_globs(globs: Option<Vec<String>>) -> MyResult<Option<GlobSet>> {
+    globs
+        .map(|globs| {
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                builder.add(Glob::new(&glob).map_err(|_| format!("Invalid glob \"{}\"", glob))?);
+            }
+            Ok(builder.build()?)
+        })
+        .transpose()
+}
+
+/// Collects the lines found in one input.
+#[derive(Debug, Default)]
+struct Matches {
+    lines: Vec<LineMatch<'static>>,
+    bytes_searched: usize,
+    /// Stop at the first selected line when that's all that's needed, which
+    /// it never is with --stats
+    first_only: bool,
+}
+
+impl Sink for Matches {
+    fn matched(&mut self, line: &LineMatch) -> MyResult<bool> {
+        self.lines.push(line.clone().into_owned());
+        Ok(!self.first_only)
+    }
+
+    fn context(&mut self, line: &LineMatch) -> MyResult<bool> {
+        self.lines.push(line.clone().into_owned());
+        Ok(true)
+    }
+}
+
+impl Matches {
+    fn selected(&self) -> impl Iterator<Item = &LineMatch<'static>> {
+        self.lines
+            .iter()
+            .filter(|line| line.kind == LineKind::Match)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.selected().next().is_none()
+    }
+
+    /// Number of selected lines, counting every line a multiline match spans
+    fn selected_lines(&self) -> usize {
+        self.selected().map(|line| line.line_count).sum()
+    }
+
+    /// Number of matches on the selected lines, which may be several per line
+    fn num_matches(&self) -> usize {
+        self.selected().map(|line| line.submatches.len()).sum()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Stats {
+    elapsed: Duration,
+    searches: usize,
+    searches_with_match: usize,
+    bytes_searched: usize,
+    bytes_printed: usize,
+    matched_lines: usize,
+    matches: usize,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.elapsed += other.elapsed;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_printed += other.bytes_printed;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+/// Output of searching one input, buffered so that inputs searched in
+/// parallel can be printed in the order they were found.
+#[derive(Debug, Default)]
+struct Report {
+    output: Vec<u8>,
+    errors: Vec<String>,
+    matched: bool,
+    stats: Stats,
+}
+
+impl Report {
+    fn error(message: String) -> Report {
+        Report {
+            errors: vec![message],
+            ..Default::default()
         }
-    };
-    for entry in entries {
-        match entry {
-            Err(e) => eprintln!("{}", e),
-            Ok(filename) => match open(&filename) {
-                Ok(file) => match find_lines(file, &config.pattern, config.invert_match) {
-                    Err(e) => eprintln!("{}", e),
-                    Ok(matches) => {
-                        if config.count {
-                            print(&filename, &format!("{}\n", matches.len()));
-                        } else {
-                            for line in &matches {
-                                print(&filename, line);
-                            }
-                        }
+    }
+}
+
+/// Searches every input and returns the exit status: 0 if any line was
+/// selected, 1 if none was, 2 if an error occurred (unless `-q` found a
+/// match).
+pub fn run(config: Config) -> MyResult<i32> {
+    let show_filename = config.files.len() > 1
+        || (config.walk.recursive && config.files.iter().any(|path| Path::new(path).is_dir()));
+    let start = Instant::now();
+    let mut matched = false;
+    let mut errored = false;
+    let mut stats = Stats::default();
+
+    // Files are discovered on one thread and searched on the others; reports
+    // are printed in discovery order as soon as all earlier ones are done.
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| -> MyResult<()> {
+        let (job_tx, job_rx) = crossbeam_channel::bounded(config.threads * 4);
+        let (report_tx, report_rx) = crossbeam_channel::unbounded();
+
+        let walker_tx = report_tx.clone();
+        let (config, done) = (&config, &done);
+        scope.spawn(move || {
+            for (index, entry) in find_files(&config.files, &config.walk).enumerate() {
+                let sent = match entry {
+                    Ok(filename) => job_tx.send((index, filename)).is_ok(),
+                    Err(e) => walker_tx
+                        .send((index, Report::error(e.to_string())))
+                        .is_ok(),
+                };
+                if !sent || done.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..config.threads {
+            let (job_rx, report_tx) = (job_rx.clone(), report_tx.clone());
+            scope.spawn(move || {
+                for (index, filename) in job_rx {
+                    let report = match config.in_place {
+                        true => edit::edit_file(config, &filename),
+                        false => search_file(config, &filename, show_filename),
+                    };
+                    if report_tx.send((index, report)).is_err() {
+                        break;
                     }
-                },
-                Err(e) => eprintln!("{}: {}", filename, e),
-            },
-        }
-    }
-    Ok(())
-}
-
-fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
-    let mut results = vec![];
-    for path in paths {
-        match path.as_str() {
-            "-" => results.push(Ok(path.to_string())),
-            _ => match fs::metadata(path) {
-                Ok(metadata) => {
-                    if metadata.is_dir() {
-                        if recursive {
-                            for entry in WalkDir::new(path)
-                                .into_iter()
-                                .flatten()
-                                .filter(|e| e.file_type().is_file())
-                            {
-                                results.push(Ok(entry.path().display().to_string()));
-                            }
-                        } else {
-                            results.push(Err(From::from(format!("{} is a directory", path))))
-                        }
-                    } else if metadata.is_file() {
-                        results.push(Ok(path.to_string()))
+                }
+            });
+        }
+        drop((job_rx, report_tx));
+
+        let separate_groups = config.prints_lines() && config.has_context();
+        let mut printed = false;
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        'reports: for (index, report) in report_rx {
+            pending.insert(index, report);
+            while let Some(report) = pending.remove(&next) {
+                next += 1;
+                if separate_groups && printed && !report.output.is_empty() {
+                    io::stdout().write_all(&format_group_separator(config))?;
+                }
+                printed |= !report.output.is_empty();
+                io::stdout().write_all(&report.output)?;
+                stats.add(&report.stats);
+                for error in &report.errors {
+                    eprintln!("{}", error);
+                }
+                matched |= report.matched;
+                errored |= !report.errors.is_empty();
+                // --stats counts every input, so they're all searched
+                if config.quiet && matched && !config.stats {
+                    done.store(true, Ordering::Relaxed);
+                    break 'reports;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    if config.json {
+        io::stdout().write_all(&json::summary(&stats, start.elapsed()))?;
+    }
+    if config.stats {
+        eprint!("{}", format_stats(&stats, start.elapsed()));
+    }
+
+    Ok(match (matched, errored) {
+        (true, _) if config.quiet => 0,
+        (_, true) => 2,
+        (true, false) => 0,
+        (false, false) => 1,
+    })
+}
+
+impl Config {
+    fn prints_lines(&self) -> bool {
+        !self.quiet && self.list_files.is_none() && !self.count && !self.json
+    }
+
+    fn has_context(&self) -> bool {
+        self.searcher.before_context > 0 || self.searcher.after_context > 0
+    }
+}
+
+fn search_file(config: &Config, filename: &str, show_filename: bool) -> Report {
+    let start = Instant::now();
+    let mut file = match open(filename, config.search_zip) {
+        Err(e) => return Report::error(format!("{}: {}", filename, e)),
+        Ok(file) => file,
+    };
+    // Like GNU grep, a NUL byte in the first block marks a file as binary,
+    // unless NUL is what terminates lines
+    let binary_offset = match config.binary_files {
+        BinaryFiles::Text => None,
+        _ if config.searcher.line_terminator == b'\0' => None,
+        _ => match file.fill_buf() {
+            Err(e) => return Report::error(format!("{}: {}", filename, e)),
+            Ok(buf) => buf.iter().position(|&b| b == 0),
+        },
+    };
+    let binary = binary_offset.is_some();
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Report::default();
+    }
+    let mut matches = Matches {
+        first_only: !config.stats
+            && (config.quiet || config.list_files.is_some() || (binary && config.prints_lines())),
+        ..Default::default()
+    };
+    match config.searcher.search_reader(file, &mut matches) {
+        Err(e) => return Report::error(format!("{}: {}", filename, e)),
+        Ok(bytes) => matches.bytes_searched = bytes,
+    }
+
+    let mut report = Report::default();
+    // Each line of a multiline match gets its own prefix
+    let print = |out: &mut Vec<u8>, line_num: Option<usize>, sep: &str, val: &[u8]| {
+        let fname = show_filename.then_some(filename);
+        let lines = val.split_inclusive(|&b| b == config.searcher.line_terminator);
+        for (i, part) in lines.enumerate() {
+            out.extend(format_prefix(config, fname, line_num.map(|n| n + i), sep));
+            out.extend(part);
+        }
+    };
+    if config.quiet {
+    } else if let Some(list_files) = &config.list_files {
+        let listed = match list_files {
+            ListFiles::WithMatches => !matches.is_empty(),
+            ListFiles::WithoutMatch => matches.is_empty(),
+        };
+        if listed {
+            report.output.extend(format_filename(config, filename));
+            report.output.push(if config.null { b'\0' } else { b'\n' });
+        }
+    } else if config.count {
+        // Inverted searches select lines without matches, so count lines
+        let count = match config.count_matches && !config.searcher.invert_match {
+            true => matches.num_matches(),
+            false => matches.selected_lines(),
+        };
+        print(
+            &mut report.output,
+            None,
+            ":",
+            format!("{}\n", count).as_bytes(),
+        );
+    } else if config.json {
+        if !matches.is_empty() {
+            report.output.extend(json::begin(filename));
+            for line in &matches.lines {
+                report.output.extend(json::line(filename, line));
+            }
+        }
+    } else if binary {
+        if !matches.is_empty() {
+            let name = match filename {
+                "-" => "(standard input)",
+                _ => filename,
+            };
+            report
+                .output
+                .extend(format!("Binary file {} matches\n", name).as_bytes());
+        }
+    } else {
+        let mut last_line_num = None;
+        for line in &matches.lines {
+            let skipped = last_line_num.is_some_and(|last| line.line_number > last + 1);
+            if skipped && config.has_context() {
+                report.output.extend(format_group_separator(config));
+            }
+            last_line_num = Some(line.line_number + line.line_count - 1);
+            let line_num = config.line_number.then_some(line.line_number);
+            match line.kind {
+                LineKind::Context => {
+                    let context = format_context(config, &line.line);
+                    print(&mut report.output, line_num, "-", &context);
+                }
+                LineKind::Match if config.only_matching => {
+                    for part in format_only_matching(config, &line.line) {
+                        print(&mut report.output, line_num, ":", &part);
                     }
                 }
-                Err(e) => results.push(Err(From::from(format!("{}:{}", path, e)))),
-            },
+                LineKind::Match => {
+                    let selected = format_line(config, &line.line);
+                    print(&mut report.output, line_num, ":", &selected);
+                }
+            }
         }
     }
-    results
+
+    report.matched = !matches.is_empty();
+    report.stats = Stats {
+        elapsed: start.elapsed(),
+        searches: 1,
+        searches_with_match: usize::from(report.matched),
+        bytes_searched: matches.bytes_searched,
+        bytes_printed: report.output.len(),
+        matched_lines: matches.selected_lines(),
+        matches: matches.num_matches(),
+    };
+    if config.json && report.matched {
+        report
+            .output
+            .extend(json::end(filename, binary_offset, &report.stats));
+    }
+    report
 }
 
-fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+fn format_filename(config: &Config, filename: &str) -> Vec<u8> {
+    match &config.colors {
+        Some(colors) => colors.filename(filename),
+        None => filename.as_bytes().to_vec(),
+    }
+}
+
+fn format_prefix(
+    config: &Config,
+    filename: Option<&str>,
+    line_num: Option<usize>,
+    sep: &str,
+) -> Vec<u8> {
+    let mut prefix = vec![];
+    let filename_sep = match config.null {
+        true => "\0",
+        false => sep,
+    };
+    match &config.colors {
+        Some(colors) => {
+            if let Some(filename) = filename {
+                prefix.extend(colors.filename(filename));
+                match config.null {
+                    true => prefix.push(b'\0'),
+                    false => prefix.extend(colors.separator(sep)),
+                }
+            }
+            if let Some(line_num) = line_num {
+                prefix.extend(colors.line_number(line_num));
+                prefix.extend(colors.separator(sep));
+            }
+        }
+        None => {
+            if let Some(filename) = filename {
+                prefix.extend(format!("{}{}", filename, filename_sep).as_bytes());
+            }
+            if let Some(line_num) = line_num {
+                prefix.extend(format!("{}{}", line_num, sep).as_bytes());
+            }
+        }
     }
+    prefix
 }
 
-fn find_lines<T: BufRead>(
-    mut file: T,
-    pattern: &Regex,
-    invert_match: bool,
-) -> MyResult<Vec<String>> {
-    let mut results1 = vec![];
-    let mut results2 = vec![];
-    let mut line = String::new();
+fn format_stats(stats: &Stats, elapsed_total: Duration) -> String {
+    format!(
+        "\n{} matches\n\
+         {} matched lines\n\
+         {} files contained matches\n\
+         {} files searched\n\
+         {} bytes searched\n\
+         {:.6} seconds spent searching\n\
+         {:.6} seconds\n",
+        stats.matches,
+        stats.matched_lines,
+        stats.searches_with_match,
+        stats.searches,
+        stats.bytes_searched,
+        stats.elapsed.as_secs_f64(),
+        elapsed_total.as_secs_f64(),
+    )
+}
 
-    loop {
-        let bytes = file.read_line(&mut line)?;
-        if bytes == 0 {
-            break;
+fn format_group_separator(config: &Config) -> Vec<u8> {
+    match &config.colors {
+        Some(colors) => [
+            colors.separator("--"),
+            vec![config.searcher.line_terminator],
+        ]
+        .concat(),
+        None => [b"--", &[config.searcher.line_terminator][..]].concat(),
+    }
+}
+
+/// Context lines can still match, e.g. when searching with -v, and any
+/// matches are highlighted with `mc`.
+fn format_context(config: &Config, line: &[u8]) -> Vec<u8> {
+    let Some(colors) = &config.colors else {
+        return line.to_vec();
+    };
+    let content = line
+        .strip_suffix(&[config.searcher.line_terminator])
+        .unwrap_or(line);
+    let mut result = vec![];
+    let mut last = 0;
+    for m in config
+        .searcher
+        .pattern
+        .find_iter(content)
+        .unwrap_or_default()
+    {
+        if m.is_empty() {
+            continue;
         }
+        result.extend(colors.context_line(&content[last..m.start]));
+        result.extend(colors.context_match(&content[m.clone()]));
+        last = m.end;
+    }
+    result.extend(colors.context_line(&content[last..]));
+    result.extend(&line[content.len()..]);
+    result
+}
 
-        if !invert_match && (pattern.captures(&line).is_some()) {
-            results1.push(line.clone());
+fn format_line(config: &Config, line: &[u8]) -> Vec<u8> {
+    if config.colors.is_none() && config.replace.is_none() {
+        return line.to_vec();
+    }
+    let content = line
+        .strip_suffix(&[config.searcher.line_terminator])
+        .unwrap_or(line);
+    let content = content.strip_suffix(b"\r").unwrap_or(content);
+    let mut result = vec![];
+    let mut last = 0;
+    let captures = config.searcher.pattern.captures_iter(content);
+    for caps in captures.unwrap_or_default() {
+        let m = caps.get(0).unwrap();
+        if m.is_empty() {
+            continue;
         }
-        if invert_match && !(pattern.captures(&line).is_some()) {
-            results2.push(line.clone());
+        result.extend(paint_line(config, &content[last..m.start]));
+        result.extend(paint_match(
+            config,
+            &render_match(config, content, &caps).unwrap_or_default(),
+        ));
+        last = m.end;
+    }
+    result.extend(paint_line(config, &content[last..]));
+    result.extend(&line[content.len()..]);
+    result
+}
+
+fn format_only_matching(config: &Config, line: &[u8]) -> Vec<Vec<u8>> {
+    config
+        .searcher
+        .pattern
+        .captures_iter(line)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|caps| caps.get(0).is_some_and(|m| !m.is_empty()))
+        .filter_map(|caps| render_match(config, line, &caps))
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            [
+                paint_match(config, &part),
+                vec![config.searcher.line_terminator],
+            ]
+            .concat()
+        })
+        .collect()
+}
+
+fn render_match(config: &Config, haystack: &[u8], caps: &Captures) -> Option<Vec<u8>> {
+    match (&config.replace, &config.only_group) {
+        (Some(template), _) => {
+            let mut dst = vec![];
+            caps.expand(template, &mut dst);
+            Some(dst)
         }
+        (None, Some(group)) => find_group(caps, group).map(|m| haystack[m].to_vec()),
+        (None, None) => caps.get(0).map(|m| haystack[m].to_vec()),
+    }
+}
+
+fn find_group(caps: &Captures, group: &str) -> Option<Range<usize>> {
+    match group.parse::<usize>() {
+        Ok(index) => caps.get(index),
+        Err(_) => caps.name(group),
+    }
+}
 
-        line.clear()
+fn paint_match(config: &Config, text: &[u8]) -> Vec<u8> {
+    match &config.colors {
+        Some(colors) => colors.selected_match(text),
+        None => text.to_vec(),
     }
+}
+
+fn paint_line(config: &Config, text: &[u8]) -> Vec<u8> {
+    match &config.colors {
+        Some(colors) => colors.selected_line(text),
+        None => text.to_vec(),
+    }
+}
+
+/// Lazily expands `paths` into the files to search, descending into
+/// directories when searching recursively.
+fn find_files<'a>(
+    paths: &'a [String],
+    opts: &'a WalkOptions,
+) -> impl Iterator<Item = MyResult<String>> + 'a {
+    paths
+        .iter()
+        .flat_map(move |path| -> Box<dyn Iterator<Item = MyResult<String>>> {
+            match path.as_str() {
+                "-" => Box::new(iter::once(Ok(path.to_string()))),
+                _ => match fs::metadata(path) {
+                    Ok(metadata) => {
+                        if metadata.is_dir() {
+                            if opts.recursive {
+                                Box::new(walk_dir(path, opts))
+                            } else {
+                                Box::new(iter::once(Err(From::from(format!(
+                                    "{} is a directory",
+                                    path
+                                )))))
+                            }
+                        } else if metadata.is_file() && opts.selects_file(Path::new(path)) {
+                            Box::new(iter::once(Ok(path.to_string())))
+                        } else {
+                            Box::new(iter::empty())
+                        }
+                    }
+                    Err(e) => Box::new(iter::once(Err(From::from(format!("{}:{}", path, e))))),
+                },
+            }
+        })
+}
 
-    match invert_match {
-        true => Ok(results2),
-        false => Ok(results1),
+fn walk_dir<'a>(path: &str, opts: &'a WalkOptions) -> impl Iterator<Item = MyResult<String>> + 'a {
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .standard_filters(!opts.no_ignore)
+        .hidden(!opts.hidden)
+        .require_git(false);
+    if opts.sort {
+        builder.sort_by_file_path(|a, b| a.cmp(b));
+    }
+    if let Some(exclude_dir) = opts.exclude_dir.clone() {
+        builder.filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            entry.depth() == 0 || !is_dir || !exclude_dir.is_match(entry.file_name())
+        });
+    }
+    builder.build().filter_map(move |result| match result {
+        Err(e) => Some(Err(From::from(e.to_string()))),
+        Ok(entry) => {
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            (is_file && opts.selects_file(entry.path()))
+                .then(|| Ok(entry.path().display().to_string()))
+        }
+    })
+}
+
+impl WalkOptions {
+    fn selects_file(&self, path: &Path) -> bool {
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        self.include
+            .as_ref()
+            .is_none_or(|globs| globs.is_match(name))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|globs| globs.is_match(name))
+    }
+}
+
+fn open(filename: &str, search_zip: bool) -> MyResult<Box<dyn BufRead>> {
+    let file: Box<dyn BufRead> = match filename {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        _ => Box::new(BufReader::new(File::open(filename)?)),
+    };
+    match search_zip {
+        true => Ok(decompress(file)?),
+        false => Ok(file),
+    }
+}
+
+/// Wraps `file` in a decoder if it starts with the magic number of a
+/// supported compression format.
+fn decompress(mut file: Box<dyn BufRead>) -> io::Result<Box<dyn BufRead>> {
+    let magic = file.fill_buf()?;
+    if magic.starts_with(b"\x1f\x8b") {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else if magic.starts_with(b"\xfd7zXZ\x00") {
+        Ok(Box::new(BufReader::new(XzDecoder::new_multi_decoder(file))))
+    } else if magic.starts_with(b"\x28\xb5\x2f\xfd") {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?)))
+    } else {
+        Ok(file)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{find_files, find_lines};
+    use super::{build_globs, find_files, WalkOptions};
     use rand::{distributions::Alphanumeric, Rng};
-    use regex::{Regex, RegexBuilder};
-    use std::io::Cursor;
-
-    #[test]
-    fn test_find_lines() {
-        let text = b"Lorem\nIpsum\r\nDOLOR";
-        let re1 = Regex::new("or").unwrap();
-        let matches = find_lines(Cursor::new(&text), &re1, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
-
-        let matches = find_lines(Cursor::new(&text), &re1, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
-
-        let re2 = RegexBuilder::new("or")
-            .case_insensitive(true)
-            .build()
-            .unwrap();
-        let matches = find_lines(Cursor::new(&text), &re2, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
-
-        let matches = find_lines(Cursor::new(&text), &re2, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
-    }
+    use std::fs;
 
     #[test]
     fn test_find_files() {
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        let recursive = WalkOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let not_recursive = WalkOptions::default();
+
+        let files: Vec<_> =
+            find_files(&["./tests/inputs/fox.txt".to_string()], &not_recursive).collect();
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
 
-        let files = find_files(&["./tests/inputs".to_string()], false);
+        let files: Vec<_> = find_files(&["./tests/inputs".to_string()], &not_recursive).collect();
         assert_eq!(files.len(), 1);
         if let Err(e) = &files[0] {
             assert_eq!(e.to_string(), "./tests/inputs is a directory");
         }
 
-        let res = find_files(&["./tests/inputs".to_string()], true);
-        let mut files: Vec<String> = res
-            .iter()
-            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
+        let mut files: Vec<String> = find_files(&["./tests/inputs".to_string()], &recursive)
+            .map(|r| r.unwrap().replace("\\", "/"))
             .collect();
         files.sort();
 
@@ -246,8 +1216,75 @@ mod tests {
             .take(7)
             .map(char::from)
             .collect();
-        let files = find_files(&[bad], false);
+        let files: Vec<_> = find_files(&[bad], &not_recursive).collect();
         assert_eq!(files.len(), 1);
         assert!(files[0].is_err());
     }
+
+    #[test]
+    fn test_find_files_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            ".gitignore",
+            "a.rs",
+            "b.txt",
+            "ignored.log",
+            ".hidden/c.rs",
+            "target/d.rs",
+            "src/e.rs",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "").unwrap();
+        }
+        fs::write(dir.path().join(".gitignore"), "*.log\ntarget/\n").unwrap();
+
+        let roots = vec![dir.path().display().to_string()];
+        let find = |opts: &WalkOptions| -> Vec<String> {
+            find_files(&roots, opts)
+                .map(|r| r.unwrap()[roots[0].len() + 1..].replace('\\', "/"))
+                .collect()
+        };
+        let globs = |globs: &[&str]| {
+            build_globs(Some(globs.iter().map(|g| g.to_string()).collect())).unwrap()
+        };
+
+        let opts = WalkOptions {
+            recursive: true,
+            sort: true,
+            ..Default::default()
+        };
+        assert_eq!(find(&opts), vec!["a.rs", "b.txt", "src/e.rs"]);
+
+        let opts = WalkOptions {
+            recursive: true,
+            sort: true,
+            no_ignore: true,
+            hidden: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            find(&opts),
+            vec![
+                ".gitignore",
+                ".hidden/c.rs",
+                "a.rs",
+                "b.txt",
+                "ignored.log",
+                "src/e.rs",
+                "target/d.rs"
+            ]
+        );
+
+        let opts = WalkOptions {
+            recursive: true,
+            sort: true,
+            no_ignore: true,
+            include: globs(&["*.rs"]),
+            exclude: globs(&["a*"]),
+            exclude_dir: globs(&["targ*"]),
+            ..Default::default()
+        };
+        assert_eq!(find(&opts), vec!["src/e.rs"]);
+    }
 }
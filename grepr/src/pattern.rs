@@ -0,0 +1,142 @@
+use crate::MyResult;
+use fancy_regex::Expander;
+use regex::bytes;
+use std::ops::Range;
+
+/// A compiled search pattern. `-P` selects a backtracking engine, which
+/// supports lookaround and backreferences but only matches UTF-8 text.
+#[derive(Debug)]
+pub enum Pattern {
+    Standard(bytes::Regex),
+    Pcre(fancy_regex::Regex),
+}
+
+/// The capture groups of a single match.
+pub enum Captures<'h> {
+    Standard(bytes::Captures<'h>),
+    /// With the offset of the run of UTF-8 that was searched
+    Pcre(fancy_regex::Captures<'h>, usize),
+}
+
+impl Pattern {
+    /// Spans of all successive non-overlapping matches. The backtracking
+    /// engine searches each run of valid UTF-8 on its own, so bytes that
+    /// aren't UTF-8 never match but don't hide the text around them.
+    pub fn find_iter(&self, haystack: &[u8]) -> MyResult<Vec<Range<usize>>> {
+        match self {
+            Pattern::Standard(re) => Ok(re.find_iter(haystack).map(|m| m.range()).collect()),
+            Pattern::Pcre(re) => {
+                let mut found = vec![];
+                let mut start = 0;
+                for chunk in haystack.utf8_chunks() {
+                    for m in re.find_iter(chunk.valid()) {
+                        let m = m?;
+                        found.push(start + m.start()..start + m.end());
+                    }
+                    start += chunk.valid().len() + chunk.invalid().len();
+                }
+                Ok(found)
+            }
+        }
+    }
+
+    pub fn captures_iter<'h>(&self, haystack: &'h [u8]) -> MyResult<Vec<Captures<'h>>> {
+        match self {
+            Pattern::Standard(re) => {
+                Ok(re.captures_iter(haystack).map(Captures::Standard).collect())
+            }
+            Pattern::Pcre(re) => {
+                let mut found = vec![];
+                let mut start = 0;
+                for chunk in haystack.utf8_chunks() {
+                    for caps in re.captures_iter(chunk.valid()) {
+                        found.push(Captures::Pcre(caps?, start));
+                    }
+                    start += chunk.valid().len() + chunk.invalid().len();
+                }
+                Ok(found)
+            }
+        }
+    }
+
+    pub fn captures_len(&self) -> usize {
+        match self {
+            Pattern::Standard(re) => re.captures_len(),
+            Pattern::Pcre(re) => re.captures_len(),
+        }
+    }
+
+    pub fn has_group(&self, name: &str) -> bool {
+        match self {
+            Pattern::Standard(re) => re.capture_names().flatten().any(|n| n == name),
+            Pattern::Pcre(re) => re.capture_names().flatten().any(|n| n == name),
+        }
+    }
+}
+
+impl Captures<'_> {
+    pub fn get(&self, index: usize) -> Option<Range<usize>> {
+        match self {
+            Captures::Standard(caps) => caps.get(index).map(|m| m.range()),
+            Captures::Pcre(caps, start) => {
+                caps.get(index).map(|m| start + m.start()..start + m.end())
+            }
+        }
+    }
+
+    pub fn name(&self, name: &str) -> Option<Range<usize>> {
+        match self {
+            Captures::Standard(caps) => caps.name(name).map(|m| m.range()),
+            Captures::Pcre(caps, start) => {
+                caps.name(name).map(|m| start + m.start()..start + m.end())
+            }
+        }
+    }
+
+    /// Appends `template` to `dst` with `$name` and `${name}` replaced by
+    /// the corresponding groups.
+    pub fn expand(&self, template: &str, dst: &mut Vec<u8>) {
+        match self {
+            Captures::Standard(caps) => caps.expand(template.as_bytes(), dst),
+            Captures::Pcre(caps, _) => {
+                let mut expanded = String::new();
+                Expander::default().append_expansion(&mut expanded, template, caps);
+                dst.extend(expanded.as_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+    use std::ops::Range;
+
+    #[test]
+    fn test_pcre() {
+        let re = Pattern::Pcre(fancy_regex::Regex::new(r"(\w)\1(?=!)").unwrap());
+        assert_eq!(re.find_iter(b"aa! bb cc!").unwrap(), vec![0..2, 7..9]);
+        assert!(re.find_iter(b"aa").unwrap().is_empty());
+        assert!(re.find_iter(b"\xff").unwrap().is_empty());
+        assert_eq!(re.find_iter(b"aa!\xffbb!").unwrap(), vec![0..2, 4..6]);
+
+        let caps = re.captures_iter(b"xx!").unwrap();
+        assert_eq!(caps.len(), 1);
+        assert_eq!(caps[0].get(1), Some(Range { start: 0, end: 1 }));
+        let mut dst = vec![];
+        caps[0].expand("<$1>", &mut dst);
+        assert_eq!(dst, b"<x>");
+
+        let caps = re.captures_iter(b"\xffyy!").unwrap();
+        assert_eq!(caps[0].get(1), Some(Range { start: 1, end: 2 }));
+
+        // Running out of backtracking is an error, not a missed match
+        let re = fancy_regex::RegexBuilder::new(r"(a+)+(?=b)")
+            .backtrack_limit(100)
+            .build()
+            .unwrap();
+        assert!(Pattern::Pcre(re)
+            .captures_iter(b"aaaaaaaaaaaaaaaa")
+            .is_err());
+    }
+}
@@ -0,0 +1,733 @@
+use crate::{pattern::Pattern, MyResult};
+use regex::bytes::RegexBuilder;
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader},
+    ops::Range,
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Match,
+    Context,
+}
+
+/// A selected line, or a line of context around one. In multiline mode, the
+/// lines spanned by a match are kept together as one. The line is borrowed
+/// from the searcher's buffer where it can be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch<'a> {
+    pub(crate) kind: LineKind,
+    pub(crate) line_number: usize,
+    pub(crate) absolute_offset: usize,
+    pub(crate) line: Cow<'a, [u8]>,
+    pub(crate) submatches: Vec<Range<usize>>,
+    pub(crate) line_count: usize,
+}
+
+impl LineMatch<'_> {
+    pub fn kind(&self) -> LineKind {
+        self.kind
+    }
+
+    /// Number of the first line, counting from 1
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Offset of the start of the line from the start of the input
+    pub fn absolute_offset(&self) -> usize {
+        self.absolute_offset
+    }
+
+    /// The line, including its terminator if it has one
+    pub fn bytes(&self) -> &[u8] {
+        &self.line
+    }
+
+    /// Spans of the pattern's matches within `bytes()`. Always empty for
+    /// context lines and lines selected by an inverted search.
+    pub fn submatches(&self) -> &[Range<usize>] {
+        &self.submatches
+    }
+
+    /// More than one when a multiline match spans several lines
+    pub fn line_count(&self) -> usize {
+        self.line_count
+    }
+
+    /// Copies the line, if it's borrowed, so it can be kept once the search
+    /// moves on.
+    pub fn into_owned(self) -> LineMatch<'static> {
+        LineMatch {
+            kind: self.kind,
+            line_number: self.line_number,
+            absolute_offset: self.absolute_offset,
+            line: Cow::Owned(self.line.into_owned()),
+            submatches: self.submatches,
+            line_count: self.line_count,
+        }
+    }
+}
+
+/// Receives the results of a search as they're found.
+///
+/// ```
+/// use grepr::{LineMatch, MyResult, Searcher, Sink};
+///
+/// struct Count(usize);
+///
+/// impl Sink for Count {
+///     fn matched(&mut self, _line: &LineMatch) -> MyResult<bool> {
+///         self.0 += 1;
+///         // Stop searching after the second match
+///         Ok(self.0 < 2)
+///     }
+/// }
+///
+/// # fn main() -> MyResult<()> {
+/// let searcher = Searcher::builder().pattern("a").build()?;
+/// let mut count = Count(0);
+/// searcher.search_reader(&b"a\nb\na\na\n"[..], &mut count)?;
+/// assert_eq!(count.0, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub trait Sink {
+    /// Called with each selected line. Returning `Ok(false)` stops the
+    /// search, and an error stops it and is returned from the search.
+    fn matched(&mut self, line: &LineMatch) -> MyResult<bool>;
+
+    /// Called with each line of context, in order with the selected lines.
+    fn context(&mut self, _line: &LineMatch) -> MyResult<bool> {
+        Ok(true)
+    }
+}
+
+/// Configures and compiles a [`Searcher`].
+#[derive(Debug, Clone)]
+pub struct SearcherBuilder {
+    patterns: Vec<String>,
+    fixed_strings: bool,
+    word_regexp: bool,
+    line_regexp: bool,
+    case_insensitive: bool,
+    multiline: bool,
+    perl_regexp: bool,
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    max_count: Option<usize>,
+    line_terminator: u8,
+}
+
+impl Default for SearcherBuilder {
+    fn default() -> Self {
+        SearcherBuilder {
+            patterns: vec![],
+            fixed_strings: false,
+            word_regexp: false,
+            line_regexp: false,
+            case_insensitive: false,
+            multiline: false,
+            perl_regexp: false,
+            invert_match: false,
+            before_context: 0,
+            after_context: 0,
+            max_count: None,
+            line_terminator: b'\n',
+        }
+    }
+}
+
+impl SearcherBuilder {
+    pub fn new() -> SearcherBuilder {
+        SearcherBuilder::default()
+    }
+
+    /// Adds a pattern. A line is selected if it matches any of them; with
+    /// none, nothing matches.
+    pub fn pattern(&mut self, pattern: &str) -> &mut SearcherBuilder {
+        self.patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn patterns<I, S>(&mut self, patterns: I) -> &mut SearcherBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.patterns
+            .extend(patterns.into_iter().map(|p| p.as_ref().to_string()));
+        self
+    }
+
+    /// Treat patterns as literal strings rather than regular expressions.
+    pub fn fixed_strings(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.fixed_strings = yes;
+        self
+    }
+
+    /// Only match whole words.
+    pub fn word_regexp(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.word_regexp = yes;
+        self
+    }
+
+    /// Only match whole lines.
+    pub fn line_regexp(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.line_regexp = yes;
+        self
+    }
+
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Search the whole input at once so matches can span lines.
+    pub fn multiline(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.multiline = yes;
+        self
+    }
+
+    /// Use a backtracking engine supporting lookaround and backreferences,
+    /// which only matches UTF-8 text.
+    pub fn perl_regexp(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.perl_regexp = yes;
+        self
+    }
+
+    /// Select the lines that don't match.
+    pub fn invert_match(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.invert_match = yes;
+        self
+    }
+
+    /// Number of lines of context to report before each selected line.
+    pub fn before_context(&mut self, lines: usize) -> &mut SearcherBuilder {
+        self.before_context = lines;
+        self
+    }
+
+    /// Number of lines of context to report after each selected line.
+    pub fn after_context(&mut self, lines: usize) -> &mut SearcherBuilder {
+        self.after_context = lines;
+        self
+    }
+
+    /// Sets both `before_context` and `after_context`.
+    pub fn context(&mut self, lines: usize) -> &mut SearcherBuilder {
+        self.before_context(lines).after_context(lines)
+    }
+
+    /// Stop after this many lines have been selected.
+    pub fn max_count(&mut self, max_count: Option<usize>) -> &mut SearcherBuilder {
+        self.max_count = max_count;
+        self
+    }
+
+    /// Byte ending each line, `\n` by default.
+    pub fn line_terminator(&mut self, terminator: u8) -> &mut SearcherBuilder {
+        self.line_terminator = terminator;
+        self
+    }
+
+    /// Compiles the patterns, failing with the first invalid one.
+    pub fn build(&self) -> MyResult<Searcher> {
+        Ok(Searcher {
+            pattern: self.build_pattern()?,
+            multiline: self.multiline,
+            invert_match: self.invert_match,
+            before_context: self.before_context,
+            after_context: self.after_context,
+            max_count: self.max_count,
+            line_terminator: self.line_terminator,
+        })
+    }
+
+    /// Combines all patterns into a single alternation. Large lists of fixed
+    /// strings stay fast because the regex engine matches an alternation of
+    /// literals with an Aho-Corasick automaton.
+    fn build_pattern(&self) -> MyResult<Pattern> {
+        let alternation = match self.patterns.is_empty() {
+            // No patterns (e.g. an empty -f file) never match
+            true => String::from("[^\\s\\S]"),
+            false => self
+                .patterns
+                .iter()
+                .map(|pattern| match self.fixed_strings {
+                    true => regex::escape(pattern),
+                    false => pattern.to_string(),
+                })
+                .map(|pattern| format!("(?:{})", pattern))
+                .collect::<Vec<_>>()
+                .join("|"),
+        };
+        let combined = if self.line_regexp {
+            format!("^(?:{})$", alternation)
+        } else if self.word_regexp {
+            // Like GNU grep, a word match is one not preceded or followed by
+            // a word character, which \b alone misses when the pattern
+            // starts or ends with punctuation
+            match self.perl_regexp {
+                true => format!("(?<!\\w)(?:{})(?!\\w)", alternation),
+                false => format!("\\b{{start-half}}(?:{})\\b{{end-half}}", alternation),
+            }
+        } else {
+            alternation
+        };
+        let build = |pattern: &str| -> MyResult<Pattern> {
+            match self.perl_regexp {
+                true => {
+                    // In multiline mode, ^ and $ match at line boundaries
+                    let pattern = match self.multiline {
+                        true => format!("(?m){}", pattern),
+                        false => pattern.to_string(),
+                    };
+                    let re = fancy_regex::RegexBuilder::new(&pattern)
+                        .case_insensitive(self.case_insensitive)
+                        .build()?;
+                    Ok(Pattern::Pcre(re))
+                }
+                false => {
+                    let re = RegexBuilder::new(pattern)
+                        .case_insensitive(self.case_insensitive)
+                        .multi_line(self.multiline)
+                        .size_limit(1 << 30)
+                        .dfa_size_limit(1 << 30)
+                        .build()?;
+                    Ok(Pattern::Standard(re))
+                }
+            }
+        };
+        build(&combined).map_err(|_| {
+            let bad = self
+                .patterns
+                .iter()
+                .find(|pattern| build(pattern).is_err())
+                .map_or(combined.as_str(), |pattern| pattern.as_str());
+            From::from(format!("Invalid pattern \"{}\"", bad))
+        })
+    }
+}
+
+/// Searches inputs for lines matching a set of patterns, passing selected
+/// lines and their context to a [`Sink`].
+///
+/// ```
+/// use grepr::{LineKind, LineMatch, MyResult, Searcher, Sink};
+///
+/// #[derive(Default)]
+/// struct Lines(Vec<(LineKind, usize, String)>);
+///
+/// impl Sink for Lines {
+///     fn matched(&mut self, line: &LineMatch) -> MyResult<bool> {
+///         self.context(line)
+///     }
+///
+///     fn context(&mut self, line: &LineMatch) -> MyResult<bool> {
+///         let text = String::from_utf8_lossy(line.bytes()).into_owned();
+///         self.0.push((line.kind(), line.line_number(), text));
+///         Ok(true)
+///     }
+/// }
+///
+/// # fn main() -> MyResult<()> {
+/// let searcher = Searcher::builder()
+///     .pattern("fox")
+///     .case_insensitive(true)
+///     .before_context(1)
+///     .build()?;
+/// let mut lines = Lines::default();
+/// searcher.search_reader(&b"The\nquick brown\nFox\n"[..], &mut lines)?;
+/// assert_eq!(
+///     lines.0,
+///     vec![
+///         (LineKind::Context, 2, "quick brown\n".to_string()),
+///         (LineKind::Match, 3, "Fox\n".to_string()),
+///     ]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Searcher {
+    pub(crate) pattern: Pattern,
+    pub(crate) multiline: bool,
+    pub(crate) invert_match: bool,
+    pub(crate) before_context: usize,
+    pub(crate) after_context: usize,
+    max_count: Option<usize>,
+    pub(crate) line_terminator: u8,
+}
+
+impl Searcher {
+    pub fn builder() -> SearcherBuilder {
+        SearcherBuilder::new()
+    }
+
+    /// Searches the file at `path`.
+    pub fn search_path<P: AsRef<Path>, S: Sink>(&self, path: P, sink: &mut S) -> MyResult<usize> {
+        self.search_reader(BufReader::new(File::open(path)?), sink)
+    }
+
+    /// Searches `reader` to the end, or until the sink stops the search or
+    /// `max_count` lines have been selected, and returns the number of bytes
+    /// searched.
+    pub fn search_reader<R: BufRead, S: Sink>(&self, reader: R, sink: &mut S) -> MyResult<usize> {
+        match self.multiline {
+            true => self.search_multiline(reader, sink),
+            false => self.search_lines(reader, sink),
+        }
+    }
+
+    fn search_lines<R: BufRead, S: Sink>(&self, mut reader: R, sink: &mut S) -> MyResult<usize> {
+        let terminator = self.line_terminator;
+        let mut selection = Selection::default();
+        // One buffer is reused for every line, so only the lines the sink
+        // keeps are copied
+        let mut line = vec![];
+        let mut line_number = 0;
+        let mut offset = 0;
+        while !self.is_done(&selection) {
+            line.clear();
+            let bytes = reader.read_until(terminator, &mut line)?;
+            if bytes == 0 {
+                break;
+            }
+            line_number += 1;
+            let content = line.strip_suffix(&[terminator]).unwrap_or(&line);
+            let line_match = LineMatch {
+                kind: LineKind::Context,
+                line_number,
+                absolute_offset: offset,
+                submatches: self.pattern.find_iter(content)?,
+                line: Cow::Borrowed(&line),
+                line_count: 1,
+            };
+            offset += bytes;
+            if !self.select(&mut selection, line_match, sink)? {
+                break;
+            }
+        }
+        Ok(selection.bytes_searched)
+    }
+
+    /// Searches the whole input at once so that matches can span lines. The
+    /// lines a match spans are grouped together, along with any further
+    /// matches that start within them.
+    fn search_multiline<R: BufRead, S: Sink>(
+        &self,
+        mut reader: R,
+        sink: &mut S,
+    ) -> MyResult<usize> {
+        let terminator = self.line_terminator;
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        let line_end = |pos: usize| {
+            buf[pos..]
+                .iter()
+                .position(|&b| b == terminator)
+                .map_or(buf.len(), |i| pos + i + 1)
+        };
+
+        let mut found = self.pattern.find_iter(&buf)?.into_iter().peekable();
+        let mut lines = vec![];
+        let mut line_number = 1;
+        let mut start = 0;
+        while start < buf.len() {
+            let mut end = line_end(start);
+            let mut submatches = vec![];
+            while let Some(m) = found.next_if(|m| m.start < end) {
+                if m.end > end {
+                    end = line_end(m.end - 1);
+                }
+                submatches.push(m.start - start..m.end - start);
+            }
+            let line = &buf[start..end];
+            let line_match = LineMatch {
+                kind: LineKind::Context,
+                line_number,
+                absolute_offset: start,
+                line: Cow::Borrowed(line),
+                submatches,
+                line_count: line.split_inclusive(|&b| b == terminator).count(),
+            };
+            line_number += line_match.line_count;
+            lines.push(line_match);
+            start = end;
+        }
+
+        let mut selection = Selection::default();
+        for line_match in lines {
+            if self.is_done(&selection) || !self.select(&mut selection, line_match, sink)? {
+                break;
+            }
+        }
+        Ok(selection.bytes_searched)
+    }
+
+    /// Whether `max_count` lines have been selected and the context after
+    /// the last of them read, so the search can stop.
+    fn is_done(&self, selection: &Selection) -> bool {
+        self.limit_reached(selection) && selection.trailing == 0
+    }
+
+    fn limit_reached(&self, selection: &Selection) -> bool {
+        self.max_count
+            .is_some_and(|max| selection.num_selected >= max)
+    }
+
+    /// Passes `line_match` to `sink` if it's selected, along with the
+    /// context before it, or if it's context after a selected line.
+    /// Returns false if the sink stopped the search.
+    fn select<S: Sink>(
+        &self,
+        selection: &mut Selection,
+        mut line_match: LineMatch,
+        sink: &mut S,
+    ) -> MyResult<bool> {
+        selection.bytes_searched = line_match.absolute_offset + line_match.line.len();
+
+        let selected = line_match.submatches.is_empty() == self.invert_match;
+        if selected && !self.limit_reached(selection) {
+            line_match.kind = LineKind::Match;
+            if self.invert_match {
+                line_match.submatches.clear();
+            }
+            for context in selection.leading.drain(..) {
+                if !sink.context(&context)? {
+                    return Ok(false);
+                }
+            }
+            if !sink.matched(&line_match)? {
+                return Ok(false);
+            }
+            selection.num_selected += 1;
+            selection.trailing = self.after_context;
+            return Ok(true);
+        }
+        line_match.submatches.clear();
+        if selection.trailing > 0 {
+            selection.trailing -= 1;
+            return sink.context(&line_match);
+        }
+        if self.before_context > 0 {
+            if selection.leading.len() == self.before_context {
+                selection.leading.pop_front();
+            }
+            selection.leading.push_back(line_match.into_owned());
+        }
+        Ok(true)
+    }
+}
+
+/// How far a search has got: the lines held back as context in case a
+/// selected line follows, the lines of context still to come after one and
+/// the number selected so far.
+#[derive(Debug, Default)]
+struct Selection {
+    leading: VecDeque<LineMatch<'static>>,
+    trailing: usize,
+    num_selected: usize,
+    bytes_searched: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineKind, LineMatch, Searcher, SearcherBuilder, Sink};
+    use crate::MyResult;
+    use std::ops::Range;
+
+    impl Sink for Vec<LineMatch<'static>> {
+        fn matched(&mut self, line: &LineMatch) -> MyResult<bool> {
+            self.push(line.clone().into_owned());
+            Ok(true)
+        }
+
+        fn context(&mut self, line: &LineMatch) -> MyResult<bool> {
+            self.matched(line)
+        }
+    }
+
+    fn search(builder: &SearcherBuilder, text: &[u8]) -> (Vec<LineMatch<'static>>, usize) {
+        let mut lines = vec![];
+        let bytes = builder
+            .build()
+            .unwrap()
+            .search_reader(text, &mut lines)
+            .unwrap();
+        (lines, bytes)
+    }
+
+    fn is_match(builder: &SearcherBuilder, haystack: &[u8]) -> bool {
+        let searcher = builder.build().unwrap();
+        !searcher.pattern.find_iter(haystack).unwrap().is_empty()
+    }
+
+    #[test]
+    fn test_search_lines() {
+        let text = b"Lorem\nIpsum\r\nDOLOR";
+        let (lines, _) = search(Searcher::builder().pattern("or"), text);
+        assert_eq!(lines.len(), 1);
+
+        let (lines, _) = search(Searcher::builder().pattern("or").invert_match(true), text);
+        assert_eq!(lines.len(), 2);
+
+        let mut builder = Searcher::builder();
+        builder.pattern("or").case_insensitive(true);
+        let (lines, _) = search(&builder, text);
+        assert_eq!(lines.len(), 2);
+
+        let (lines, _) = search(builder.clone().invert_match(true), text);
+        assert_eq!(lines.len(), 1);
+
+        // Stops once max_count lines are selected
+        let (lines, _) = search(builder.clone().max_count(Some(1)), text);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].bytes(), b"Lorem\n");
+        assert_eq!(lines[0].submatches(), &[Range { start: 1, end: 3 }]);
+
+        let (lines, _) = search(builder.clone().max_count(Some(0)), text);
+        assert!(lines.is_empty());
+
+        // Context lines surround selected lines without being counted
+        let text = b"a\nb\nfoo\nc\nd\ne\nfoo\n";
+        let mut builder = Searcher::builder();
+        builder
+            .pattern("foo")
+            .max_count(Some(1))
+            .before_context(1)
+            .after_context(2);
+        let (lines, bytes) = search(&builder, text);
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|line| (line.line_number(), line.kind(), line.absolute_offset()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (2, LineKind::Context, 2),
+                (3, LineKind::Match, 4),
+                (4, LineKind::Context, 8),
+                (5, LineKind::Context, 10),
+            ]
+        );
+        assert_eq!(bytes, 12);
+
+        // Lines can be terminated by NUL instead
+        let text = b"one\nfoo\0two\0foo";
+        let (lines, _) = search(
+            Searcher::builder().pattern("foo").line_terminator(b'\0'),
+            text,
+        );
+        let lines: Vec<_> = lines.iter().map(LineMatch::bytes).collect();
+        assert_eq!(lines, vec![&b"one\nfoo\0"[..], &b"foo"[..]]);
+    }
+
+    #[test]
+    fn test_search_multiline() {
+        let text = b"fn main() {\n    body\n}\nfn other() {}\nrest\n";
+        let mut builder = Searcher::builder();
+        builder.pattern(r"(?s)fn \w+\(\) \{.*?\}").multiline(true);
+        let (lines, bytes) = search(&builder, text);
+        let spans: Vec<_> = lines
+            .iter()
+            .map(|line| {
+                (
+                    line.line_number(),
+                    line.line_count(),
+                    line.absolute_offset(),
+                )
+            })
+            .collect();
+        assert_eq!(spans, vec![(1, 3, 0), (4, 1, 23)]);
+        assert_eq!(lines[0].submatches(), &[Range { start: 0, end: 22 }]);
+        assert_eq!(bytes, text.len());
+
+        let (lines, _) = search(builder.clone().invert_match(true), text);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].bytes(), b"rest\n");
+
+        let (lines, _) = search(builder.clone().max_count(Some(1)).after_context(1), text);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].kind(), LineKind::Context);
+        assert_eq!(lines[1].line_number(), 4);
+    }
+
+    #[test]
+    fn test_sink_stops_search() {
+        struct First(Option<usize>);
+        impl Sink for First {
+            fn matched(&mut self, line: &LineMatch) -> MyResult<bool> {
+                self.0 = Some(line.line_number());
+                Ok(false)
+            }
+        }
+
+        let searcher = Searcher::builder().pattern("b").build().unwrap();
+        let mut first = First(None);
+        let bytes = searcher
+            .search_reader(&b"a\nb\nb\nc\n"[..], &mut first)
+            .unwrap();
+        assert_eq!(first.0, Some(2));
+        assert_eq!(bytes, 4);
+    }
+
+    #[test]
+    fn test_build_pattern() {
+        let mut builder = Searcher::builder();
+        builder.patterns(["a.c", "x+"]);
+
+        assert!(is_match(&builder, b"abc"));
+        assert!(is_match(&builder, b"xx"));
+        assert!(!is_match(&builder, b"ac"));
+
+        let fixed = builder.clone().fixed_strings(true).clone();
+        assert!(!is_match(&fixed, b"abc"));
+        assert!(is_match(&fixed, b"a.c"));
+        assert!(is_match(&fixed, b"x+"));
+
+        let word = builder.clone().word_regexp(true).clone();
+        assert!(is_match(&word, b"an abc here"));
+        assert!(!is_match(&word, b"abcd"));
+
+        // Patterns may start or end with non-word characters
+        let mut punct = Searcher::builder();
+        punct
+            .patterns(["foo.", "-x"])
+            .fixed_strings(true)
+            .word_regexp(true);
+        for perl in [false, true] {
+            let punct = punct.clone().perl_regexp(perl).clone();
+            assert!(is_match(&punct, b"foo. bar"));
+            assert!(is_match(&punct, b"a -x"));
+            assert!(!is_match(&punct, b"foo.bar"));
+            assert!(!is_match(&punct, b"a-x"));
+            assert!(!is_match(&punct, b"xfoo."));
+        }
+
+        let line = builder.clone().line_regexp(true).clone();
+        assert!(is_match(&line, b"xxx"));
+        assert!(!is_match(&line, b"xxx abc"));
+
+        let empty = Searcher::builder();
+        assert!(!is_match(&empty, b""));
+        assert!(!is_match(&empty, b"abc"));
+
+        let res = Searcher::builder().patterns(["ok", "*x"]).build();
+        assert_eq!(res.unwrap_err().to_string(), "Invalid pattern \"*x\"");
+
+        // Lookaround needs the backtracking engine
+        let mut lookbehind = Searcher::builder();
+        lookbehind.pattern(r"(?<=\$)\d+").word_regexp(true);
+        assert!(lookbehind.build().is_err());
+        lookbehind.perl_regexp(true);
+        assert!(is_match(&lookbehind, b"costs $10"));
+        assert!(!is_match(&lookbehind, b"costs 10"));
+    }
+}
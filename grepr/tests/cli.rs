@@ -1,3 +1,5 @@
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
@@ -12,6 +14,12 @@ const EMPTY: &str = "tests/inputs/empty.txt";
 const FOX: &str = "tests/inputs/fox.txt";
 const NOBODY: &str = "tests/inputs/nobody.txt";
 const INPUTS_DIR: &str = "tests/inputs";
+const BINARY: &str = "tests/binary/nul.bin";
+const LATIN1: &str = "tests/binary/latin1.txt";
+const ZIP_DIR: &str = "tests/zip";
+const BUSTLE_GZ: &str = "tests/zip/bustle.txt.gz";
+const BUSTLE_XZ: &str = "tests/zip/bustle.txt.xz";
+const FOX_ZST: &str = "tests/zip/fox.txt.zst";
 
 // --------------------------------------------------
 fn gen_bad_file() -> String {
@@ -277,3 +285,896 @@ fn stdin_insensitive_count() -> TestResult {
         .stdout(expected);
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn line_numbers() -> TestResult {
+    run(
+        &["-n", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_always() -> TestResult {
+    run(
+        &["--color=always", "-n", "The", BUSTLE, FOX],
+        "tests/expected/all.the.capitalized.color.n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_never() -> TestResult {
+    run(
+        &["--color=never", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_auto_not_terminal() -> TestResult {
+    run(
+        &["--color", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn color_grep_colors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "fox", FOX])
+        .env("GREP_COLORS", "mt=04:ne")
+        .assert()
+        .success()
+        .stdout("The quick brown \x1b[04mfox\x1b[m jumps over the lazy dog.\n");
+    // Matches in context lines, which -v makes of matching lines
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "-v", "-A1", "foo"])
+        .env("GREP_COLORS", "ms=04:mc=33:ne")
+        .write_stdin("a\nfoo\n")
+        .assert()
+        .success()
+        .stdout("a\n\x1b[33mfoo\x1b[m\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-oi", "the", FOX])
+        .assert()
+        .success()
+        .stdout("The\nthe\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_matching_multiple_files() -> TestResult {
+    let expected = "tests/inputs/bustle.txt:2:The m\n\
+        tests/inputs/fox.txt:1:fox\n";
+    Command::cargo_bin(PRG)?
+        .args(["-on", "fox|The m", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_group_index() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--only-group", "1", r"(\w+) fox", FOX])
+        .assert()
+        .success()
+        .stdout("brown\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn only_group_name() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--only-group", "adj", r"the (?P<adj>\w+) dog", FOX])
+        .assert()
+        .success()
+        .stdout("lazy\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_only_group() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--only-group", "2", r"(\w+) fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --only-group \"2\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "${adj}-$1", r"(\w+) (?P<adj>\w+) dog", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over lazy-the.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_only_matching() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-o", "--replace", "<$1>", r"(\w+)ing", BUSTLE])
+        .assert()
+        .success()
+        .stdout("<morn>\n<sweep>\n<putt>\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status_no_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["nobody", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status_error() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["fox", FOX, &bad])
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("fox"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exit_status_bad_args() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "x", "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid --max-count \"x\""));
+    Command::cargo_bin(PRG)?
+        .args(["--bogus", "fox", FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", FOX])
+        .assert()
+        .code(0)
+        .stdout("");
+    Command::cargo_bin(PRG)?
+        .args(["-q", "nobody", FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_match_with_error() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", &bad, FOX])
+        .assert()
+        .code(0)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "The", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n{}\n{}\n", BUSTLE, FOX, NOBODY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-L", "The", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", EMPTY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "2", "The", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The bustle in a house\nThe morning after death\n");
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--max-count", "1", "The", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n{}:1\n", BUSTLE, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_regexps() -> TestResult {
+    let expected = "tests/inputs/bustle.txt:The bustle in a house\n\
+        tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n";
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "--regexp", "bustle", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn regexp_reads_stdin() -> TestResult {
+    let input = fs::read_to_string(FOX)?;
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox"])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(input);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pattern_file() -> TestResult {
+    let expected = "tests/inputs/bustle.txt:The morning after death\n\
+        tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n";
+    Command::cargo_bin(PRG)?
+        .args(["-f", "tests/patterns.txt", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_pattern_file() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", bad);
+    Command::cargo_bin(PRG)?
+        .args(["-f", &bad, FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-F", "-e", "dog.", "-e", "a*", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\n");
+    Command::cargo_bin(PRG)?
+        .args(["-F", "a.", FOX])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-w", "hous", BUSTLE])
+        .assert()
+        .code(1);
+    Command::cargo_bin(PRG)?
+        .args(["-w", "house", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The bustle in a house\n");
+    // Punctuation at the edge of the pattern still matches
+    Command::cargo_bin(PRG)?
+        .args(["-F", "-w", "dog.", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\n");
+    Command::cargo_bin(PRG)?
+        .args(["-F", "-w", "lazy d", FOX])
+        .assert()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-x", "The bustle", BUSTLE])
+        .assert()
+        .code(1);
+    Command::cargo_bin(PRG)?
+        .args(["-xi", "the bustle in a house", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The bustle in a house\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_sorted() -> TestResult {
+    let expected = format!("{}\n{}\n{}\n", BUSTLE, FOX, NOBODY);
+    for threads in ["1", "4"] {
+        Command::cargo_bin(PRG)?
+            .args(["-rl", "--sort", "path", "-j", threads, "the", INPUTS_DIR])
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_include_exclude() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-rl", "--include", "f*", "the", INPUTS_DIR])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", FOX));
+    Command::cargo_bin(PRG)?
+        .args(["-rl", "--sort=path", "--exclude", "[bf]*", "the", INPUTS_DIR])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", NOBODY));
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--exclude-dir", "inputs", "the", "tests"])
+        .assert()
+        .stdout(predicate::str::is_match("(?m)^tests/inputs/")?.not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_threads() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-j", "0", "the", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid --threads \"0\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["data", BINARY])
+        .assert()
+        .success()
+        .stdout("Binary file tests/binary/nul.bin matches\n");
+    Command::cargo_bin(PRG)?
+        .args(["nothing", BINARY])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "data", BINARY, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:2\n{}:0\n", BINARY, FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_text() -> TestResult {
+    for flag in ["-a", "--binary-files=text"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "data", BINARY])
+            .assert()
+            .success()
+            .stdout("some data\nmore data\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_files_without_match() -> TestResult {
+    for flag in ["-I", "--binary-files=without-match"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "-l", "a", BINARY, LATIN1])
+            .assert()
+            .success()
+            .stdout(format!("{}\n", LATIN1));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "lait", LATIN1])
+        .assert()
+        .success()
+        .stdout(&b"1:caf\xe9 au lait\n"[..]);
+    Command::cargo_bin(PRG)?
+        .args(["-o", "(?-u:na.)", LATIN1])
+        .assert()
+        .success()
+        .stdout(&b"na\xef\n"[..]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "-C", "1", "sweeping", BUSTLE])
+        .assert()
+        .success()
+        .stdout("5-\n6:The sweeping up the heart,\n7-And putting love away\n");
+    Command::cargo_bin(PRG)?
+        .args(["-A1", "-B0", "The", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{b}:The bustle in a house\n\
+             {b}:The morning after death\n\
+             {b}-Is solemnest of industries\n\
+             --\n\
+             {b}:The sweeping up the heart,\n\
+             {b}-And putting love away\n\
+             --\n\
+             {f}:The quick brown fox jumps over the lazy dog.\n",
+            b = BUSTLE,
+            f = FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context_max_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m1", "-A2", "The", BUSTLE])
+        .assert()
+        .success()
+        .stdout("The bustle in a house\nThe morning after death\nIs solemnest of industries\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["--json", "-B1", "morning", BUSTLE, FOX])
+        .output()?;
+    assert!(output.status.success());
+    let messages = output
+        .stdout
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    let types: Vec<_> = messages.iter().map(|m| m["type"].clone()).collect();
+    assert_eq!(types, ["begin", "context", "match", "end", "summary"]);
+
+    let data = &messages[2]["data"];
+    assert_eq!(data["path"]["text"], BUSTLE);
+    assert_eq!(data["lines"]["text"], "The morning after death\n");
+    assert_eq!(data["line_number"], 2);
+    assert_eq!(data["absolute_offset"], 22);
+    assert_eq!(data["submatches"][0]["match"]["text"], "morning");
+    assert_eq!(data["submatches"][0]["start"], 4);
+    assert_eq!(data["submatches"][0]["end"], 11);
+
+    let stats = &messages[4]["data"]["stats"];
+    assert_eq!(stats["searches"], 2);
+    assert_eq!(stats["searches_with_match"], 1);
+    assert_eq!(stats["matched_lines"], 1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_invalid_utf8() -> TestResult {
+    let output = Command::cargo_bin(PRG)?
+        .args(["--json", "lait", LATIN1])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(r#""lines":{"bytes":"Y2Fm6SBhdSBsYWl0Cg=="}"#));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_conflicts() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--json", "-c", "fox", FOX])
+        .assert()
+        .failure()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "-U", "(?s)sweeping.*?love", BUSTLE])
+        .assert()
+        .success()
+        .stdout("6:The sweeping up the heart,\n7:And putting love away\n");
+    Command::cargo_bin(PRG)?
+        .args(["-c", "--multiline", "after death\nIs", BUSTLE])
+        .assert()
+        .success()
+        .stdout("2\n");
+    // Without -U, patterns never match across lines
+    Command::cargo_bin(PRG)?
+        .args(["-c", "after death\nIs", BUSTLE])
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-P", r"(\w)\1", BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "The sweeping up the heart,\n\
+             And putting love away\n\
+             We shall not want to use again\n",
+        );
+    Command::cargo_bin(PRG)?
+        .args(["-o", "--perl-regexp", r"(?<=The )\w+(?= up)", BUSTLE])
+        .assert()
+        .success()
+        .stdout("sweeping\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_regexp_invalid_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-n", "-o", r"(?<=\s)l\w+", LATIN1])
+        .assert()
+        .success()
+        .stdout("1:lait\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip() -> TestResult {
+    for file in [BUSTLE_GZ, BUSTLE_XZ] {
+        Command::cargo_bin(PRG)?
+            .args(["-z", "-n", "sweeping", file])
+            .assert()
+            .success()
+            .stdout("6:The sweeping up the heart,\n");
+    }
+    Command::cargo_bin(PRG)?
+        .args(["--search-zip", "fox", FOX_ZST, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:The quick brown fox jumps over the lazy dog.\n\
+             {}:The quick brown fox jumps over the lazy dog.\n",
+            FOX_ZST, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip_recursive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-zr", "--sort", "path", "-c", "The", ZIP_DIR])
+        .assert()
+        .success()
+        .stdout(format!("{}:3\n{}:3\n{}:1\n", BUSTLE_GZ, BUSTLE_XZ, FOX_ZST));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn search_zip_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-zc", "The"])
+        .write_stdin(fs::read(BUSTLE_GZ)?)
+        .assert()
+        .success()
+        .stdout("3\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn compressed_without_search_zip() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["sweeping", BUSTLE_GZ])
+        .assert()
+        .failure()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_filenames() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-lZ", "The", BUSTLE, EMPTY, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\0{}\0", BUSTLE, FOX));
+    Command::cargo_bin(PRG)?
+        .args(["--null", "-n", "fox", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\x001:The quick brown fox jumps over the lazy dog.\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_filenames_recursive() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a\nb.txt"), "foo\n")?;
+    fs::write(dir.path().join("c d.txt"), "foo\n")?;
+    fs::write(dir.path().join("e.txt"), "bar\n")?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args(["-rlZ", "--sort", "path", "foo", root])
+        .assert()
+        .success()
+        .stdout(format!("{r}/a\nb.txt\0{r}/c d.txt\0", r = root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--null-data", "-n", "fox"])
+        .write_stdin("a fox\nand a dog\0a cat\0another fox")
+        .assert()
+        .success()
+        .stdout("1:a fox\nand a dog\x003:another fox");
+    // NUL bytes no longer make input binary
+    Command::cargo_bin(PRG)?
+        .args(["--null-data", "-c", "data", BINARY])
+        .assert()
+        .success()
+        .stdout("1\n");
+    // -z is taken by --search-zip, so lines still end at newlines
+    Command::cargo_bin(PRG)?
+        .args(["-z", "-c", "The", BUSTLE])
+        .assert()
+        .success()
+        .stdout("3\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("a.txt");
+    let nested = dir.path().join("sub/b.txt");
+    fs::create_dir(dir.path().join("sub"))?;
+    fs::write(&file, "hello world\nbye world\nnothing\n")?;
+    fs::write(&nested, "world\n")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640))?;
+    }
+
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--replace", "<$1>", "--in-place", "--backup", ".orig"])
+        .args(["w(or)ld", dir.path().to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(&file)?, "hello <or>\nbye <or>\nnothing\n");
+    assert_eq!(fs::read_to_string(&nested)?, "<or>\n");
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt.orig"))?,
+        "hello world\nbye world\nnothing\n"
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&file)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    // Nothing left to replace
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "x", "--in-place", "world"])
+        .arg(&file)
+        .assert()
+        .failure()
+        .code(1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_dry_run() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "one\ntwo\nthree\nfour\nfive\nsix\n")?;
+    let filename = file.to_str().unwrap();
+
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "TWO", "--in-place", "--dry-run", "two", filename])
+        .assert()
+        .success()
+        .stdout(format!(
+            "--- {f}\n+++ {f}\n@@ -1,5 +1,5 @@\n one\n-two\n+TWO\n three\n four\n five\n",
+            f = filename
+        ));
+    assert_eq!(
+        fs::read_to_string(&file)?,
+        "one\ntwo\nthree\nfour\nfive\nsix\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_errors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "y", "--in-place", "x"])
+        .write_stdin("x\n")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr("-: standard input can't be edited in place\n");
+    // --in-place needs something to replace with
+    Command::cargo_bin(PRG)?
+        .args(["--in-place", "x", FOX])
+        .assert()
+        .failure()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_stats() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("a.txt");
+    fs::write(&file, "a b\nc\nd\n")?;
+    // Changed lines are counted once however many matches they have
+    Command::cargo_bin(PRG)?
+        .args(["-U", "--replace", "X", "--in-place", "--stats", "a|b|c"])
+        .arg(&file)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "\n3 matches\n2 matched lines\n",
+        ));
+    assert_eq!(fs::read_to_string(&file)?, "X X\nX\nd\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place_perl_regexp_invalid_utf8() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("a.txt");
+    fs::write(&file, b"caf\xe9 au lait\n")?;
+    // The text around bytes that aren't UTF-8 is still edited
+    Command::cargo_bin(PRG)?
+        .args(["-P", "--replace", "milk", "--in-place", "(?<=au )lait"])
+        .arg(&file)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&file)?, b"caf\xe9 au milk\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-matches", "-i", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}:4\n{}:2\n", BUSTLE, FOX));
+    // Inverted searches have no matches to count, so lines are counted
+    Command::cargo_bin(PRG)?
+        .args(["--count-matches", "-v", "the", FOX])
+        .assert()
+        .failure()
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stats() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--stats", "-i", "the", BUSTLE, FOX, EMPTY])
+        .assert()
+        .success()
+        .stderr(
+            predicate::str::contains(
+                "\n6 matches\n\
+                 4 matched lines\n\
+                 2 files contained matches\n\
+                 3 files searched\n\
+                 238 bytes searched\n",
+            )
+            .and(predicate::str::is_match(r"(?m)^\d+\.\d{6} seconds$")?),
+        );
+    // Counts are complete even when the output needs only the first match
+    for flag in ["-l", "-q"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "--stats", "-i", "the", BUSTLE, FOX, EMPTY])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "\n6 matches\n\
+                 4 matched lines\n\
+                 2 files contained matches\n\
+                 3 files searched\n\
+                 238 bytes searched\n",
+            ));
+    }
+    Ok(())
+}